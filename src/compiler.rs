@@ -0,0 +1,22 @@
+use crate::Error;
+
+// a small structured language that compiles down to Brainfuck:
+//
+//   # comments start with a hash
+//   n = 10;
+//   while n > 0 {
+//     if n % 2 == 0 { print "even\n"; } else { print "odd\n"; }
+//     n = n - 1;
+//   }
+//   read c;
+//   print c + 1;
+//
+// values are cells, so arithmetic wraps around like the cells do
+// and the generated code relies on cells wrapping
+
+mod syntax; pub use syntax::*;
+mod codegen; pub use codegen::*;
+
+pub fn compile_to_bf(source: &str) -> Result<String, Error> {
+  generate(&parse_program(source)?)
+}
@@ -0,0 +1,415 @@
+use crate::{Error, Expression, Operator, Statement};
+use std::collections::HashMap;
+
+// constants up to this are written out, bigger ones are built digit by digit in this base
+const BASE: u32 = 16;
+
+// variables live in the first cells, temporaries are stacked after them and are always
+// left null when they're released, so a new temporary never needs clearing
+#[derive(Default)]
+struct Codegen {
+  bf: String,
+  pointer: usize,
+  variables: HashMap<String, usize>,
+  next: usize
+}
+
+impl Codegen {
+  fn goto(&mut self, cell: usize) {
+    if cell > self.pointer {
+      self.bf += &">".repeat(cell - self.pointer);
+    } else {
+      self.bf += &"<".repeat(self.pointer - cell);
+    }
+    self.pointer = cell;
+  }
+
+  fn add(&mut self, cell: usize, n: i64) {
+    self.goto(cell);
+    if n >= 0 {
+      self.bf += &"+".repeat(n as usize);
+    } else {
+      self.bf += &"-".repeat(-n as usize);
+    }
+  }
+
+  // multiplies what's been built so far by the base before adding each digit,
+  // so the code grows with the number of digits rather than with the number
+  fn constant(&mut self, cell: usize, n: u32) {
+    if n <= BASE {
+      self.add(cell, n as i64);
+      return;
+    }
+    self.constant(cell, n / BASE);
+    let temporary = self.allocate();
+    self.transfer(cell, &[(temporary, 1)]);
+    self.transfer(temporary, &[(cell, BASE as i64)]);
+    self.release(temporary);
+    self.add(cell, (n % BASE) as i64);
+  }
+
+  fn clear(&mut self, cell: usize) {
+    self.goto(cell);
+    self.bf += "[-]";
+  }
+
+  fn open(&mut self, cell: usize) {
+    self.goto(cell);
+    self.bf.push('[');
+  }
+
+  // loops have to end on the cell they started on for the pointer to stay known
+  fn close(&mut self, cell: usize) {
+    self.goto(cell);
+    self.bf.push(']');
+  }
+
+  fn allocate(&mut self) -> usize {
+    self.next += 1;
+    self.next - 1
+  }
+
+  fn release(&mut self, cell: usize) {
+    debug_assert_eq!(cell + 1, self.next, "temporaries are released in reverse order");
+    self.next -= 1;
+  }
+
+  // empties from into every target, multiplied by its factor
+  fn transfer(&mut self, from: usize, targets: &[(usize, i64)]) {
+    self.open(from);
+    self.add(from, -1);
+    for &(cell, factor) in targets {
+      self.add(cell, factor);
+    }
+    self.close(from);
+  }
+
+  fn copy(&mut self, from: usize, to: usize) {
+    let temporary = self.allocate();
+    self.transfer(from, &[(to, 1), (temporary, 1)]);
+    self.transfer(temporary, &[(from, 1)]);
+    self.release(temporary);
+  }
+
+  fn not(&mut self, cell: usize) {
+    let result = self.allocate();
+    self.add(result, 1);
+    self.open(cell);
+    self.clear(cell);
+    self.add(result, -1);
+    self.close(cell);
+    self.transfer(result, &[(cell, 1)]);
+    self.release(result);
+  }
+
+  fn is_not_null(&mut self, cell: usize) {
+    let result = self.allocate();
+    self.open(cell);
+    self.clear(cell);
+    self.add(result, 1);
+    self.close(cell);
+    self.transfer(result, &[(cell, 1)]);
+    self.release(result);
+  }
+
+  // both cells count down together, x < y when x reaches zero first,
+  // x ends up holding the result and y ends up null
+  fn less(&mut self, x: usize, y: usize) {
+    let result = self.allocate();
+    let flag = self.allocate();
+    let temporary = self.allocate();
+    self.open(y);
+    self.add(flag, 1);
+    self.copy(x, temporary);
+    self.open(temporary);
+    self.add(x, -1);
+    self.add(flag, -1);
+    self.clear(temporary);
+    self.close(temporary);
+    self.open(flag);
+    self.add(result, 1);
+    self.clear(y);
+    self.add(y, 1);
+    self.add(flag, -1);
+    self.close(flag);
+    self.add(y, -1);
+    self.close(y);
+    self.clear(x);
+    self.transfer(result, &[(x, 1)]);
+    self.release(temporary);
+    self.release(flag);
+    self.release(result);
+  }
+
+  fn at_least(&mut self, x: usize, y: usize, result: usize) {
+    let (x2, y2) = (self.allocate(), self.allocate());
+    self.copy(x, x2);
+    self.copy(y, y2);
+    self.less(x2, y2);
+    self.transfer(x2, &[(result, 1)]);
+    self.not(result);
+    self.release(y2);
+    self.release(x2);
+  }
+
+  // repeated subtraction, so dividing by zero never terminates
+  fn divide(&mut self, x: usize, y: usize, remainder: bool) {
+    let quotient = self.allocate();
+    let condition = self.allocate();
+    self.at_least(x, y, condition);
+    self.open(condition);
+    let temporary = self.allocate();
+    self.copy(y, temporary);
+    self.transfer(temporary, &[(x, -1)]);
+    self.release(temporary);
+    self.add(quotient, 1);
+    self.clear(condition);
+    self.at_least(x, y, condition);
+    self.close(condition);
+    if remainder {
+      self.clear(quotient);
+    } else {
+      self.clear(x);
+      self.transfer(quotient, &[(x, 1)]);
+    }
+    self.clear(y);
+    self.release(condition);
+    self.release(quotient);
+  }
+
+  fn expression(&mut self, expression: &Expression) -> Result<usize, Error> {
+    let cell = match expression {
+      Expression::Number(number) => {
+        let cell = self.allocate();
+        self.constant(cell, *number);
+        cell
+      }
+      Expression::Variable(name, position) => match self.variables.get(name) {
+        None => return Err(Error::InvalidSyntax(*position, format!("'{}' is used before it's given a value", name))),
+        Some(&variable) => {
+          let cell = self.allocate();
+          self.copy(variable, cell);
+          cell
+        }
+      }
+      Expression::Not(expression) => {
+        let cell = self.expression(expression)?;
+        self.not(cell);
+        cell
+      }
+      Expression::Binary(left, operator, right) => {
+        let x = self.expression(left)?;
+        let y = self.expression(right)?;
+        match operator {
+          Operator::Add => self.transfer(y, &[(x, 1)]),
+          Operator::Subtract => self.transfer(y, &[(x, -1)]),
+          Operator::Multiply => {
+            let product = self.allocate();
+            let temporary = self.allocate();
+            self.open(y);
+            self.transfer(x, &[(product, 1), (temporary, 1)]);
+            self.transfer(temporary, &[(x, 1)]);
+            self.add(y, -1);
+            self.close(y);
+            self.clear(x);
+            self.transfer(product, &[(x, 1)]);
+            self.release(temporary);
+            self.release(product);
+          }
+          Operator::Divide => self.divide(x, y, false),
+          Operator::Remainder => self.divide(x, y, true),
+          Operator::Equal => {
+            self.transfer(y, &[(x, -1)]);
+            self.not(x);
+          }
+          Operator::NotEqual => {
+            self.transfer(y, &[(x, -1)]);
+            self.is_not_null(x);
+          }
+          Operator::Less => self.less(x, y),
+          Operator::Greater => {
+            self.less(y, x);
+            self.transfer(y, &[(x, 1)]);
+          }
+          Operator::LessOrEqual => {
+            self.less(y, x);
+            self.transfer(y, &[(x, 1)]);
+            self.not(x);
+          }
+          Operator::GreaterOrEqual => {
+            self.less(x, y);
+            self.not(x);
+          }
+        }
+        self.release(y);
+        x
+      }
+    };
+    Ok(cell)
+  }
+
+  fn statements(&mut self, statements: &[Statement]) -> Result<(), Error> {
+    for statement in statements {
+      self.statement(statement)?;
+      self.bf.push('\n');
+    }
+    Ok(())
+  }
+
+  fn statement(&mut self, statement: &Statement) -> Result<(), Error> {
+    match statement {
+      Statement::Assign(name, value) => {
+        let cell = self.expression(value)?;
+        let variable = self.variables[name];
+        self.clear(variable);
+        self.transfer(cell, &[(variable, 1)]);
+        self.release(cell);
+      }
+      Statement::While(condition, body) => {
+        let cell = self.expression(condition)?;
+        self.open(cell);
+        self.statements(body)?;
+        let next = self.expression(condition)?;
+        self.clear(cell);
+        self.transfer(next, &[(cell, 1)]);
+        self.release(next);
+        self.close(cell);
+        self.release(cell);
+      }
+      Statement::If(condition, body, otherwise) => {
+        let cell = self.expression(condition)?;
+        let other = if otherwise.is_empty() { None } else { Some(self.allocate()) };
+        if let Some(other) = other {
+          self.add(other, 1);
+        }
+        self.open(cell);
+        self.statements(body)?;
+        if let Some(other) = other {
+          self.add(other, -1);
+        }
+        self.clear(cell);
+        self.close(cell);
+        if let Some(other) = other {
+          self.open(other);
+          self.statements(otherwise)?;
+          self.add(other, -1);
+          self.close(other);
+          self.release(other);
+        }
+        self.release(cell);
+      }
+      Statement::Print(Expression::Variable(name, position)) => match self.variables.get(name) {
+        None => return Err(Error::InvalidSyntax(*position, format!("'{}' is used before it's given a value", name))),
+        Some(&variable) => {
+          self.goto(variable);
+          self.bf.push('.');
+        }
+      }
+      Statement::Print(value) => {
+        let cell = self.expression(value)?;
+        self.goto(cell);
+        self.bf.push('.');
+        self.clear(cell);
+        self.release(cell);
+      }
+      Statement::PrintText(text) => {
+        let cell = self.allocate();
+        let mut current = 0;
+        for char in text.chars() {
+          self.add(cell, char as i64 - current);
+          self.bf.push('.');
+          current = char as i64;
+        }
+        if current != 0 {
+          self.clear(cell);
+        }
+        self.release(cell);
+      }
+      Statement::Read(name) => {
+        self.goto(self.variables[name]);
+        self.bf.push(',');
+      }
+    }
+    Ok(())
+  }
+}
+
+fn declare(statements: &[Statement], variables: &mut HashMap<String, usize>) {
+  for statement in statements {
+    match statement {
+      Statement::Assign(name, _)
+      | Statement::Read(name) => {
+        let next = variables.len();
+        variables.entry(name.clone()).or_insert(next);
+      }
+      Statement::While(_, body) => declare(body, variables),
+      Statement::If(_, body, otherwise) => {
+        declare(body, variables);
+        declare(otherwise, variables);
+      }
+      _ => {}
+    }
+  }
+}
+
+pub fn generate(statements: &[Statement]) -> Result<String, Error> {
+  let mut codegen = Codegen::default();
+  declare(statements, &mut codegen.variables);
+  codegen.next = codegen.variables.len();
+  codegen.statements(statements)?;
+  Ok(codegen.bf)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{lex, optimise, parse, parse_program, sample_run, Args, Memory32};
+  use clap::Clap;
+
+  // the output and the tape once the program ends, on 32-bit cells so big constants fit
+  fn run(bf: &str) -> (String, Vec<u32>) {
+    let args = Args::parse_from(["brainfuck", "--cell-size", "32", "--memory-size", "16"]);
+    let (result, output, cells) = sample_run(&optimise(parse(&lex(bf)).unwrap()), &mut Memory32::new(&args).unwrap());
+    result.unwrap();
+    (output, cells)
+  }
+
+  // into the first cell, which a variable would take
+  fn constant(n: u32) -> String {
+    let mut codegen = Codegen { next: 1, ..Codegen::default() };
+    codegen.constant(0, n);
+    codegen.goto(0);
+    codegen.bf
+  }
+
+  #[test]
+  fn small_constants_are_written_out() {
+    assert_eq!(constant(0), "");
+    assert_eq!(constant(BASE), "+".repeat(BASE as usize));
+  }
+
+  #[test]
+  fn big_constants_are_built_digit_by_digit() {
+    for n in [BASE + 1, 255, 1000, 65537] {
+      let bf = constant(n);
+      assert!(bf.len() < 400, "{} takes {} commands", n, bf.len());
+      let (_, cells) = run(&bf);
+      assert_eq!(cells[0], n);
+      assert!(cells[1..].iter().all(|&cell| cell == 0), "{} leaves a temporary behind", n);
+    }
+    assert!(constant(u32::MAX).len() < 400);
+  }
+
+  #[test]
+  fn programs() {
+    let bf = generate(&parse_program("n = 3; while n > 0 { print n + 48; n = n - 1; } print \"!\";").unwrap()).unwrap();
+    assert_eq!(run(&bf).0, "321!");
+    let bf = generate(&parse_program("x = 300; if x % 7 == 6 { print 'y'; } else { print 'n'; }").unwrap()).unwrap();
+    assert_eq!(run(&bf).0, "y");
+  }
+
+  #[test]
+  fn variables_without_a_value() {
+    assert!(matches!(generate(&parse_program("print y;").unwrap()), Err(Error::InvalidSyntax(_, _))));
+  }
+}
@@ -0,0 +1,325 @@
+use crate::{Error, Position};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lexeme {
+  Name(String),
+  Number(u32),
+  Text(String),
+  Symbol(&'static str)
+}
+
+const SYMBOLS: &[&str] = &["==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "=", "(", ")", "{", "}", ";"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+  Remainder,
+  Equal,
+  NotEqual,
+  Less,
+  Greater,
+  LessOrEqual,
+  GreaterOrEqual
+}
+
+#[derive(Debug)]
+pub enum Expression {
+  Number(u32),
+  Variable(String, Position),
+  Not(Box<Expression>),
+  Binary(Box<Expression>, Operator, Box<Expression>)
+}
+
+#[derive(Debug)]
+pub enum Statement {
+  Assign(String, Expression),
+  While(Expression, Vec<Statement>),
+  If(Expression, Vec<Statement>, Vec<Statement>),
+  Print(Expression),
+  PrintText(String),
+  Read(String)
+}
+
+fn escape(char: char) -> Option<char> {
+  match char {
+    'n' => Some('\n'),
+    't' => Some('\t'),
+    '0' => Some('\0'),
+    '\\' | '"' | '\'' => Some(char),
+    _ => None
+  }
+}
+
+fn tokenise(source: &str) -> Result<Vec<(Lexeme, Position)>, Error> {
+  let mut lexemes = vec![];
+  let chars: Vec<char> = source.chars().collect();
+  let mut position = Position { line: 1, column: 1 };
+  let mut i = 0;
+  let advance = |i: &mut usize, position: &mut Position| {
+    if chars[*i] == '\n' {
+      position.line += 1;
+      position.column = 1;
+    } else {
+      position.column += 1;
+    }
+    *i += 1;
+  };
+  while i < chars.len() {
+    let start = position;
+    let char = chars[i];
+    if char.is_whitespace() {
+      advance(&mut i, &mut position);
+    } else if char == '#' {
+      while i < chars.len() && chars[i] != '\n' {
+        advance(&mut i, &mut position);
+      }
+    } else if char.is_ascii_digit() {
+      let mut number = String::new();
+      while i < chars.len() && chars[i].is_ascii_digit() {
+        number.push(chars[i]);
+        advance(&mut i, &mut position);
+      }
+      match number.parse() {
+        Err(_) => return Err(Error::InvalidSyntax(start, format!("{} doesn't fit in 32 bits", number))),
+        Ok(number) => lexemes.push((Lexeme::Number(number), start))
+      }
+    } else if char.is_alphabetic() || char == '_' {
+      let mut name = String::new();
+      while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+        name.push(chars[i]);
+        advance(&mut i, &mut position);
+      }
+      lexemes.push((Lexeme::Name(name), start));
+    } else if char == '"' || char == '\'' {
+      let mut text = String::new();
+      advance(&mut i, &mut position);
+      loop {
+        match chars.get(i) {
+          None | Some('\n') => return Err(Error::InvalidSyntax(start, String::from("Unterminated literal"))),
+          Some(&quote) if quote == char => break,
+          Some('\\') => {
+            advance(&mut i, &mut position);
+            match chars.get(i).copied().and_then(escape) {
+              None => return Err(Error::InvalidSyntax(position, String::from("Unknown escape sequence"))),
+              Some(char) => text.push(char)
+            }
+          }
+          Some(&char) => text.push(char)
+        }
+        advance(&mut i, &mut position);
+      }
+      advance(&mut i, &mut position);
+      if char == '"' {
+        lexemes.push((Lexeme::Text(text), start));
+      } else {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+          (Some(char), None) => lexemes.push((Lexeme::Number(char as u32), start)),
+          _ => return Err(Error::InvalidSyntax(start, String::from("A character literal holds exactly one character")))
+        }
+      }
+    } else {
+      let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+      match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+        None => return Err(Error::InvalidSyntax(start, format!("Unexpected character '{}'", char))),
+        Some(symbol) => {
+          for _ in 0..symbol.len() {
+            advance(&mut i, &mut position);
+          }
+          lexemes.push((Lexeme::Symbol(symbol), start));
+        }
+      }
+    }
+  }
+  Ok(lexemes)
+}
+
+struct Parser {
+  lexemes: Vec<(Lexeme, Position)>,
+  index: usize,
+  end: Position
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Lexeme> {
+    self.lexemes.get(self.index).map(|(lexeme, _)| lexeme)
+  }
+
+  fn position(&self) -> Position {
+    self.lexemes.get(self.index).map_or(self.end, |(_, position)| *position)
+  }
+
+  fn next(&mut self) -> Option<Lexeme> {
+    let lexeme = self.peek().cloned();
+    self.index += 1;
+    lexeme
+  }
+
+  fn error<T>(&self, expected: &str) -> Result<T, Error> {
+    let found = match self.peek() {
+      None => String::from("the end of the file"),
+      Some(Lexeme::Name(name)) => format!("'{}'", name),
+      Some(Lexeme::Number(number)) => number.to_string(),
+      Some(Lexeme::Text(_)) => String::from("a string"),
+      Some(Lexeme::Symbol(symbol)) => format!("'{}'", symbol)
+    };
+    Err(Error::InvalidSyntax(self.position(), format!("Expected {}, found {}", expected, found)))
+  }
+
+  fn eat(&mut self, symbol: &str) -> bool {
+    if matches!(self.peek(), Some(Lexeme::Symbol(found)) if *found == symbol) {
+      self.index += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn expect(&mut self, symbol: &str) -> Result<(), Error> {
+    if self.eat(symbol) {
+      Ok(())
+    } else {
+      self.error(&format!("'{}'", symbol))
+    }
+  }
+
+  fn name(&mut self) -> Result<String, Error> {
+    match self.peek() {
+      Some(Lexeme::Name(name)) if !KEYWORDS.contains(&name.as_str()) => {
+        let name = name.clone();
+        self.index += 1;
+        Ok(name)
+      }
+      _ => self.error("a variable")
+    }
+  }
+
+  fn block(&mut self) -> Result<Vec<Statement>, Error> {
+    self.expect("{")?;
+    let mut statements = vec![];
+    while !self.eat("}") {
+      if self.peek().is_none() {
+        return self.error("'}'");
+      }
+      statements.push(self.statement()?);
+    }
+    Ok(statements)
+  }
+
+  fn statement(&mut self) -> Result<Statement, Error> {
+    match self.peek() {
+      Some(Lexeme::Name(keyword)) if keyword == "while" => {
+        self.index += 1;
+        let condition = self.expression()?;
+        Ok(Statement::While(condition, self.block()?))
+      }
+      Some(Lexeme::Name(keyword)) if keyword == "if" => {
+        self.index += 1;
+        let condition = self.expression()?;
+        let body = self.block()?;
+        let otherwise = match self.peek() {
+          Some(Lexeme::Name(keyword)) if keyword == "else" => {
+            self.index += 1;
+            if matches!(self.peek(), Some(Lexeme::Name(keyword)) if keyword == "if") {
+              vec![self.statement()?]
+            } else {
+              self.block()?
+            }
+          }
+          _ => vec![]
+        };
+        Ok(Statement::If(condition, body, otherwise))
+      }
+      Some(Lexeme::Name(keyword)) if keyword == "print" => {
+        self.index += 1;
+        let statement = match self.peek() {
+          Some(Lexeme::Text(text)) => {
+            let text = text.clone();
+            self.index += 1;
+            Statement::PrintText(text)
+          }
+          _ => Statement::Print(self.expression()?)
+        };
+        self.expect(";")?;
+        Ok(statement)
+      }
+      Some(Lexeme::Name(keyword)) if keyword == "read" => {
+        self.index += 1;
+        let name = self.name()?;
+        self.expect(";")?;
+        Ok(Statement::Read(name))
+      }
+      _ => {
+        let name = self.name()?;
+        self.expect("=")?;
+        let value = self.expression()?;
+        self.expect(";")?;
+        Ok(Statement::Assign(name, value))
+      }
+    }
+  }
+
+  fn binary(&mut self, operators: &[(&str, Operator)], operand: fn(&mut Self) -> Result<Expression, Error>) -> Result<Expression, Error> {
+    let mut expression = operand(self)?;
+    'outer: loop {
+      for (symbol, operator) in operators {
+        if self.eat(symbol) {
+          expression = Expression::Binary(Box::new(expression), *operator, Box::new(operand(self)?));
+          continue 'outer;
+        }
+      }
+      return Ok(expression);
+    }
+  }
+
+  fn expression(&mut self) -> Result<Expression, Error> {
+    self.binary(&[
+      ("==", Operator::Equal), ("!=", Operator::NotEqual), ("<=", Operator::LessOrEqual),
+      (">=", Operator::GreaterOrEqual), ("<", Operator::Less), (">", Operator::Greater)
+    ], Self::sum)
+  }
+
+  fn sum(&mut self) -> Result<Expression, Error> {
+    self.binary(&[("+", Operator::Add), ("-", Operator::Subtract)], Self::product)
+  }
+
+  fn product(&mut self) -> Result<Expression, Error> {
+    self.binary(&[("*", Operator::Multiply), ("/", Operator::Divide), ("%", Operator::Remainder)], Self::unary)
+  }
+
+  fn unary(&mut self) -> Result<Expression, Error> {
+    if self.eat("!") {
+      return Ok(Expression::Not(Box::new(self.unary()?)));
+    }
+    if self.eat("(") {
+      let expression = self.expression()?;
+      self.expect(")")?;
+      return Ok(expression);
+    }
+    let position = self.position();
+    match self.next() {
+      Some(Lexeme::Number(number)) => Ok(Expression::Number(number)),
+      Some(Lexeme::Name(name)) if !KEYWORDS.contains(&name.as_str()) => Ok(Expression::Variable(name, position)),
+      _ => {
+        self.index -= 1;
+        self.error("an expression")
+      }
+    }
+  }
+}
+
+const KEYWORDS: &[&str] = &["while", "if", "else", "print", "read"];
+
+pub fn parse_program(source: &str) -> Result<Vec<Statement>, Error> {
+  let lexemes = tokenise(source)?;
+  let end = lexemes.last().map_or(Position { line: 1, column: 1 }, |(_, position)| *position);
+  let mut parser = Parser { lexemes, index: 0, end };
+  let mut statements = vec![];
+  while parser.peek().is_some() {
+    statements.push(parser.statement()?);
+  }
+  Ok(statements)
+}
@@ -0,0 +1,463 @@
+use crate::{tape, TAPE_RADIUS, Change, Error, History, Instruction, Memory, Position, Snapshot, State, Watchpoint};
+use std::io::{stdin, stdout, Write};
+use colored::Colorize;
+
+const HELP: &str = "\
+commands:
+  step [n]              Execute the next n steps (default: 1), entering a loop is two steps
+  continue              Execute until a breakpoint is reached or the program ends
+  back [n]              Undo the last n steps (default: 1)
+  reverse-continue      Undo steps until a breakpoint is reached or the program is back at the start
+  break <line:column>   Break whenever the instruction at this position is reached
+  break <step>          Break when this step is reached
+  delete                Remove every breakpoint
+  watch <cells>[=value] Break whenever a cell changes or becomes a value, ranges are written <from>-<to>
+  log <cells>[=value]   Print whenever a cell changes or becomes a value without breaking
+  unwatch               Remove every watchpoint
+  last-write <cell>     Print the last step that wrote to this cell
+  save <file>           Save the execution state to a file, resume it with 'brainfuck resume <file>'
+  tape                  Print the cells around the pointer
+  where                 Print the current step, pointer and instruction
+  quit                  Stop debugging";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+  Position(Position),
+  Step(u64)
+}
+
+fn body(instruction: Option<&Instruction>) -> Option<&[Instruction]> {
+  match instruction {
+    Some(Instruction::Loop(body, _))
+    | Some(Instruction::InfiniteLoop(body, _))
+    | Some(Instruction::Procedure(body, _)) => Some(body),
+    _ => None
+  }
+}
+
+fn block<'a>(instructions: &'a [Instruction], path: &[usize]) -> &'a [Instruction] {
+  let mut block = instructions;
+  for &index in path {
+    block = body(block.get(index)).expect("the debugger path always goes through loops");
+  }
+  block
+}
+
+fn history<T, M: Memory<T>>(memory: &mut M) -> &mut History {
+  let runtime = memory.runtime_mut();
+  runtime.instrumented = true;
+  runtime.history.get_or_insert_with(History::default)
+}
+
+#[derive(Debug)]
+pub struct Debugger<'a> {
+  instructions: &'a [Instruction],
+  hash: u64,
+  path: Vec<usize>,
+  breakpoints: Vec<Breakpoint>,
+  error: Option<Error>
+}
+
+impl<'a> Debugger<'a> {
+  pub fn new(instructions: &'a [Instruction], hash: u64) -> Debugger<'a> {
+    Debugger {
+      instructions,
+      hash,
+      path: vec![0],
+      breakpoints: vec![],
+      error: None
+    }
+  }
+
+  // resumes from a path saved in a state file, which might not match the program
+  pub fn with_path(instructions: &'a [Instruction], hash: u64, path: Vec<usize>) -> Option<Debugger<'a>> {
+    let (&index, parents) = path.split_last()?;
+    let mut block = instructions;
+    for &parent in parents {
+      block = body(block.get(parent))?;
+    }
+    if index > block.len() {
+      return None;
+    }
+    Some(Debugger { path, ..Debugger::new(instructions, hash) })
+  }
+
+  // a cursor at the same place, for a thread forked from this one
+  pub fn fork(&self) -> Debugger<'a> {
+    Debugger { path: self.path.clone(), ..Debugger::new(self.instructions, self.hash) }
+  }
+
+  pub fn path(&self) -> &[usize] {
+    &self.path
+  }
+
+  // the next instruction to execute, or the loop about to be checked again
+  // when the cursor is at the end of its body
+  pub fn current(&self) -> Option<&'a Instruction> {
+    let (&index, parents) = self.path.split_last().unwrap();
+    let instructions = block(self.instructions, parents);
+    if index < instructions.len() {
+      Some(&instructions[index])
+    } else if let Some((&parent, grandparents)) = parents.split_last() {
+      Some(&block(self.instructions, grandparents)[parent])
+    } else {
+      None
+    }
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.current().is_none()
+  }
+
+  fn hit(&self, from: u64, to: u64) -> Option<Breakpoint> {
+    let position = self.current().map(Instruction::position);
+    self.breakpoints.iter().copied().find(|breakpoint| match *breakpoint {
+      Breakpoint::Position(breakpoint) => position == Some(breakpoint),
+      Breakpoint::Step(step) => from < step && step <= to
+    })
+  }
+
+  // forward
+
+  pub fn step<T, M: Memory<T>>(&mut self, memory: &mut M) -> Result<(), Error> {
+    let steps = memory.runtime().steps;
+    if memory.runtime().history.as_ref().is_some_and(History::wants_snapshot) {
+      let pointer = memory.pointer();
+      let cells = memory.cells();
+      history(memory).snapshot(steps, self.path.clone(), pointer, cells);
+    }
+    if let Some(history) = &mut memory.runtime_mut().history {
+      history.begin(steps, self.path.clone());
+    }
+    let depth = self.path.len();
+    let index = self.path[depth - 1];
+    let instructions = block(self.instructions, &self.path[..depth - 1]);
+    if let Some(instruction) = instructions.get(index) {
+      match instruction {
+        Instruction::Loop(_, position) | Instruction::InfiniteLoop(_, position) => {
+          memory.step(instruction)?;
+          if memory.current_is_null() {
+            self.path[depth - 1] += 1;
+          } else {
+            memory.iterate(*position)?;
+            self.path.push(0);
+          }
+        }
+        // defining a procedure only remembers where its body is
+        Instruction::Procedure(_, _) => {
+          memory.step(instruction)?;
+          let number = M::value_to_u32(memory.current());
+          memory.runtime_mut().procedures.insert(number, self.path.clone());
+          self.path[depth - 1] += 1;
+        }
+        Instruction::Call(position) => {
+          memory.step(instruction)?;
+          let number = M::value_to_u32(memory.current());
+          let mut procedure = match memory.runtime().procedures.get(&number) {
+            None => return Err(Error::UndefinedProcedure(number, *position)),
+            Some(procedure) => procedure.clone()
+          };
+          self.path[depth - 1] += 1;
+          memory.runtime_mut().call(self.path.clone())?;
+          procedure.push(0);
+          self.path = procedure;
+        }
+        _ => {
+          instruction.run(memory)?;
+          self.path[depth - 1] += 1;
+        }
+      }
+    } else if let Some(Instruction::Procedure(_, _)) = self.current() {
+      self.path = memory.runtime_mut().calls.pop().expect("procedure bodies are only entered by calls");
+    } else if let Some(instruction) = self.current() {
+      let position = instruction.position();
+      if memory.current_is_null() {
+        self.path.pop();
+        *self.path.last_mut().unwrap() += 1;
+      } else if let (Instruction::InfiniteLoop(_, _), false) = (instruction, memory.args().wrap_around) {
+        return Err(Error::InfiniteLoop(position));
+      } else {
+        memory.iterate(position)?;
+        self.path[depth - 1] = 0;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn run_to_end<T, M: Memory<T>>(&mut self, memory: &mut M) -> Result<(), Error> {
+    while !self.is_finished() {
+      self.step(memory)?;
+    }
+    Ok(())
+  }
+
+  // counts runtime steps like the step shown, breakpoints and last-write, an instruction
+  // is never split so this goes one step past when it ends by entering a loop
+  fn forward<T, M: Memory<T>>(&mut self, memory: &mut M, n: u64, breakpoints: bool) {
+    let target = memory.runtime().steps.saturating_add(n);
+    while memory.runtime().steps < target && self.error.is_none() && !self.is_finished() {
+      let from = memory.runtime().steps;
+      if let Err(err) = self.step(memory) {
+        self.error = Some(err);
+        return;
+      }
+      if let Some(hit) = memory.runtime_mut().hit.take() {
+        println!("{} {} ({})", "debugger:".green(), hit, hit.watchpoint);
+        return;
+      }
+      if breakpoints {
+        if let Some(breakpoint) = self.hit(from, memory.runtime().steps) {
+          self.print_breakpoint(breakpoint);
+          return;
+        }
+      }
+    }
+  }
+
+  // backward
+
+  fn restore<T, M: Memory<T>>(&mut self, memory: &mut M, snapshot: Snapshot) {
+    for (pointer, &value) in snapshot.cells.iter().enumerate() {
+      memory.put_value(pointer as u32, M::value_from_u32(value));
+    }
+    *memory.pointer_mut() = snapshot.pointer;
+    memory.runtime_mut().steps = snapshot.steps;
+    self.path = snapshot.path;
+    self.error = None;
+  }
+
+  pub fn step_back<T, M: Memory<T>>(&mut self, memory: &mut M) -> bool {
+    match history(memory).pop() {
+      None => false,
+      Some(record) => {
+        for change in record.changes.iter().rev() {
+          match *change {
+            Change::Pointer(pointer) => *memory.pointer_mut() = pointer,
+            Change::Cell(pointer, value) => memory.put_value(pointer, M::value_from_u32(value))
+          }
+        }
+        memory.runtime_mut().steps = record.steps;
+        self.path = record.path;
+        self.error = None;
+        true
+      }
+    }
+  }
+
+  fn backward<T, M: Memory<T>>(&mut self, memory: &mut M, n: u64, breakpoints: bool) {
+    if !breakpoints {
+      if n == 0 {
+        return;
+      }
+      let steps = memory.runtime().steps.saturating_sub(n);
+      let target = history(memory).kept_at(steps);
+      if let Some(snapshot) = history(memory).rewind(target) {
+        self.restore(memory, snapshot);
+      }
+      while history(memory).len() > target {
+        self.step_back(memory);
+      }
+      return;
+    }
+    loop {
+      let to = memory.runtime().steps;
+      if !self.step_back(memory) {
+        println!("{} reached the start of the program", "debugger:".green());
+        return;
+      }
+      if let Some(breakpoint) = self.hit(memory.runtime().steps, to) {
+        self.print_breakpoint(breakpoint);
+        return;
+      }
+    }
+  }
+
+  // commands
+
+  fn print_breakpoint(&self, breakpoint: Breakpoint) {
+    match breakpoint {
+      Breakpoint::Position(position) => println!("{} hit breakpoint at position {}", "debugger:".green(), position.to_string().yellow()),
+      Breakpoint::Step(step) => println!("{} hit breakpoint at step {}", "debugger:".green(), step.to_string().yellow())
+    }
+  }
+
+  fn print_where<T, M: Memory<T>>(&self, memory: &M) {
+    let next = match (&self.error, self.current()) {
+      (Some(err), _) => format!("{} {}", "runtime error:".red(), err.message()),
+      (None, None) => String::from("end of program"),
+      (None, Some(instruction)) => format!("{} at {}", instruction.to_string().yellow(), instruction.position().to_string().green())
+    };
+    println!("{} [step: {}, pointer: {}, value: {}] {}",
+      "debugger:".green(),
+      memory.runtime().steps.to_string().green(),
+      memory.pointer().to_string().green(),
+      M::value_to_string(memory.current()).green(),
+      next
+    );
+  }
+
+  fn print_tape<T, M: Memory<T>>(&self, memory: &M) {
+    println!("{} {}", "debugger:".green(), tape(memory, TAPE_RADIUS));
+  }
+
+  fn print_last_write<T, M: Memory<T>>(&self, memory: &mut M, pointer: u32) {
+    match history(memory).last_write(pointer) {
+      None => println!("{} cell {} hasn't been written to", "debugger:".green(), pointer.to_string().green()),
+      Some(record) => println!("{} cell {} was last written to at step {}", "debugger:".green(), pointer.to_string().green(), (record.steps + 1).to_string().green())
+    }
+  }
+
+  fn parse_count(argument: Option<&str>) -> Option<u64> {
+    match argument {
+      None => Some(1),
+      Some(argument) => argument.parse().ok()
+    }
+  }
+
+  fn parse_breakpoint(argument: &str) -> Option<Breakpoint> {
+    match argument.split_once(':') {
+      Some((line, column)) => Some(Breakpoint::Position(Position {
+        line: line.parse().ok()?,
+        column: column.parse().ok()?
+      })),
+      None => Some(Breakpoint::Step(argument.parse().ok()?))
+    }
+  }
+
+  #[allow(unused_must_use)]
+  pub fn run<T, M: Memory<T>>(&mut self, memory: &mut M) -> Result<(), Error> {
+    println!("{} type 'help' for a list of commands", "debugger:".green());
+    self.print_where(memory);
+    loop {
+      print!("{} ", "(debug)".green());
+      stdout().flush();
+      let mut line = String::new();
+      match stdin().read_line(&mut line) {
+        Err(_) => return Err(Error::ReadInputFail),
+        Ok(0) => return Ok(()),
+        Ok(_) => {}
+      }
+      let mut words = line.split_whitespace();
+      let command = match words.next() {
+        None => continue,
+        Some(command) => command
+      };
+      let argument = words.next();
+      match command {
+        "step" | "s" => match Self::parse_count(argument) {
+          None => println!("{} invalid step count", "debugger:".red()),
+          Some(n) => self.forward(memory, n, false)
+        }
+        "continue" | "c" => self.forward(memory, u64::MAX, true),
+        "back" | "step-back" | "b" => match Self::parse_count(argument) {
+          None => println!("{} invalid step count", "debugger:".red()),
+          Some(n) => self.backward(memory, n, false)
+        }
+        "reverse-continue" | "rc" => self.backward(memory, u64::MAX, true),
+        "break" => match argument.and_then(Self::parse_breakpoint) {
+          None => println!("{} expected a step or a line:column position", "debugger:".red()),
+          Some(breakpoint) => self.breakpoints.push(breakpoint)
+        }
+        "delete" => self.breakpoints.clear(),
+        "watch" | "log" => match argument.map(str::parse::<Watchpoint>) {
+          None => println!("{} expected <cells>[=value]", "debugger:".red()),
+          Some(Err(err)) => println!("{} {}", "debugger:".red(), err),
+          Some(Ok(watchpoint)) => memory.runtime_mut().watchpoints.push(Watchpoint { pause: command == "watch", ..watchpoint })
+        }
+        "unwatch" => memory.runtime_mut().watchpoints.clear(),
+        "last-write" | "lw" => match argument.and_then(|argument| argument.parse().ok()) {
+          None => println!("{} expected a cell", "debugger:".red()),
+          Some(pointer) => self.print_last_write(memory, pointer)
+        }
+        "save" => match argument {
+          None => println!("{} expected a file", "debugger:".red()),
+          Some(file) => match State::capture(memory, self.hash, &self.path).and_then(|state| state.save(file)) {
+            Err(err) => println!("{} {}", "debugger:".red(), err.message()),
+            Ok(()) => println!("{} saved the execution state to {}", "debugger:".green(), file.yellow())
+          }
+        }
+        "tape" | "t" => self.print_tape(memory),
+        "where" | "w" => {}
+        "help" | "h" => println!("{}", HELP),
+        "quit" | "q" => return Ok(()),
+        _ => println!("{} unknown command '{}', type 'help' for a list of commands", "debugger:".red(), command)
+      }
+      stdout().flush();
+      self.print_where(memory);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{lex, optimise, parse, Args, Memory8};
+  use clap::Clap;
+
+  fn args() -> Args {
+    Args::parse_from(["brainfuck"])
+  }
+
+  #[test]
+  fn saved_paths() {
+    let instructions = parse(&lex("+[-[>]]")).unwrap();
+    assert!(Debugger::with_path(&instructions, 0, vec![1, 1, 0]).is_some());
+    assert!(Debugger::with_path(&instructions, 0, vec![2]).is_some());
+    assert!(Debugger::with_path(&instructions, 0, vec![3]).is_none());
+    assert!(Debugger::with_path(&instructions, 0, vec![0, 0]).is_none());
+    assert!(Debugger::with_path(&instructions, 0, vec![1, 2, 0]).is_none());
+    assert!(Debugger::with_path(&instructions, 0, vec![]).is_none());
+  }
+
+  #[test]
+  fn steps_through_loops() {
+    let instructions = parse(&lex("+[-]>")).unwrap();
+    let mut debugger = Debugger::new(&instructions, 0);
+    let args = args();
+    let mut memory = Memory8::new(&args).unwrap();
+    let mut paths = vec![debugger.path().to_vec()];
+    while !debugger.is_finished() {
+      debugger.step(&mut memory).unwrap();
+      paths.push(debugger.path().to_vec());
+    }
+    assert_eq!(paths, [vec![0], vec![1], vec![1, 0], vec![1, 1], vec![2], vec![3]]);
+    assert!(debugger.current().is_none());
+    assert_eq!(memory.pointer(), 1);
+  }
+
+  #[test]
+  fn the_end_of_a_body_checks_its_loop_again() {
+    let instructions = parse(&lex("++[-]")).unwrap();
+    let mut debugger = Debugger::with_path(&instructions, 0, vec![2, 1]).unwrap();
+    assert!(matches!(debugger.current(), Some(Instruction::Loop(_, _))));
+    let args = args();
+    let mut memory = Memory8::new(&args).unwrap();
+    memory.put_value(0, 1);
+    debugger.step(&mut memory).unwrap();
+    assert_eq!(debugger.path(), [2, 0]);
+    assert_eq!(debugger.fork().path(), [2, 0]);
+  }
+
+  #[test]
+  fn steps_back_to_the_start() {
+    let instructions = parse(&lex("++[>+<-]")).unwrap();
+    let mut debugger = Debugger::new(&instructions, 0);
+    let args = args();
+    let mut memory = Memory8::new(&args).unwrap();
+    history(&mut memory);
+    debugger.run_to_end(&mut memory).unwrap();
+    assert_eq!(memory.cells()[..2], [0, 2]);
+    while debugger.step_back(&mut memory) {}
+    assert_eq!(debugger.path(), [0]);
+    assert_eq!(memory.cells()[..2], [0, 0]);
+    assert_eq!((memory.pointer(), memory.runtime().steps), (0, 0));
+  }
+
+  #[test]
+  fn infinite_loops() {
+    let instructions = optimise(parse(&lex("+[>+<]")).unwrap());
+    let mut debugger = Debugger::new(&instructions, 0);
+    let args = args();
+    assert!(matches!(debugger.run_to_end(&mut Memory8::new(&args).unwrap()), Err(Error::InfiniteLoop(Position { line: 1, column: 2 }))));
+  }
+}
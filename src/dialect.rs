@@ -0,0 +1,245 @@
+use crate::{Error, Token};
+use std::fs;
+
+const COMMANDS: &[(char, Token)] = &[
+  ('+', Token::Increment),
+  ('-', Token::Decrement),
+  ('>', Token::MoveRight),
+  ('<', Token::MoveLeft),
+  ('.', Token::Write),
+  (',', Token::Read),
+  ('[', Token::EnterLoop),
+  (']', Token::ExitLoop)
+];
+
+// the spelling of every command, in the order of COMMANDS
+const BRAINFUCK: [&str; 8] = ["+", "-", ">", "<", ".", ",", "[", "]"];
+const OOK: [&str; 8] = ["Ook. Ook.", "Ook! Ook!", "Ook. Ook?", "Ook? Ook.", "Ook! Ook.", "Ook. Ook!", "Ook! Ook?", "Ook? Ook!"];
+const ALPHUCK: [&str; 8] = ["e", "i", "a", "c", "j", "o", "p", "s"];
+// Boolfuck has no - since + flips a bit, and writes with ; instead of .
+const BOOLFUCK: &[(&str, Token)] = &[
+  ("+", Token::Increment),
+  (">", Token::MoveRight),
+  ("<", Token::MoveLeft),
+  (";", Token::Write),
+  (",", Token::Read),
+  ("[", Token::EnterLoop),
+  ("]", Token::ExitLoop)
+];
+
+// a trivial substitution of the eight commands, plus the extra commands of extensions like Brainfork,
+// a space in a command matches any whitespace so that words like Ook! can be split across lines
+#[derive(Debug, Clone)]
+pub struct Dialect {
+  pub name: String,
+  commands: Vec<(Vec<String>, Token)>
+}
+
+impl Dialect {
+  fn new(name: &str, spellings: &[String]) -> Dialect {
+    let commands = spellings.iter().zip(COMMANDS)
+      .map(|(spelling, (_, token))| (spelling.split_whitespace().map(String::from).collect(), token.clone()))
+      .collect();
+    Dialect { name: name.to_string(), commands }.sorted()
+  }
+
+  // longer spellings first, so one that starts like another still gets matched
+  fn sorted(mut self) -> Dialect {
+    self.commands.sort_by_key(|(words, _)| std::cmp::Reverse(words.iter().map(String::len).sum::<usize>()));
+    self
+  }
+
+  fn with(mut self, spelling: &str, token: Token) -> Dialect {
+    self.commands.push((spelling.split_whitespace().map(String::from).collect(), token));
+    self.sorted()
+  }
+
+  pub fn brainfuck() -> Dialect {
+    Dialect::new("brainfuck", &BRAINFUCK.map(String::from))
+  }
+
+  pub fn builtin(name: &str) -> Option<Dialect> {
+    match name {
+      "brainfuck" => Some(Dialect::brainfuck()),
+      "ook" => Some(Dialect::new(name, &OOK.map(String::from))),
+      "blub" => Some(Dialect::new(name, &OOK.map(|spelling| spelling.replace("Ook", "Blub")))),
+      "alphuck" => Some(Dialect::new(name, &ALPHUCK.map(String::from))),
+      "brainfork" => Some(Dialect::new(name, &BRAINFUCK.map(String::from)).with("Y", Token::Fork)),
+      "pbrain" => Some(Dialect::new(name, &BRAINFUCK.map(String::from))
+        .with("(", Token::StartProcedure)
+        .with(")", Token::EndProcedure)
+        .with(":", Token::Call)),
+      "extended" => Some(Dialect::new(name, &BRAINFUCK.map(String::from))
+        .with("@", Token::End)
+        .with("$", Token::Store)
+        .with("!", Token::Load)
+        .with("}", Token::ShiftRight)
+        .with("{", Token::ShiftLeft)
+        .with("~", Token::Not)
+        .with("^", Token::Xor)
+        .with("&", Token::And)
+        .with("|", Token::Or)),
+      "boolfuck" => Some(BOOLFUCK.iter().fold(Dialect::new(name, &[]), |dialect, (spelling, token)| dialect.with(spelling, token.clone()))),
+      _ => None
+    }
+  }
+
+  // one command per line, written '<command> = <spelling>', with # starting a comment
+  pub fn load(path: &str) -> Result<Dialect, Error> {
+    let invalid = |reason: String| Error::InvalidDialect(path.to_string(), reason);
+    let content = match fs::read_to_string(path) {
+      Err(_) => return Err(Error::UnknownDialect(path.to_string())),
+      Ok(content) => content
+    };
+    let mut spellings: [Option<String>; 8] = Default::default();
+    for (index, line) in content.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let (command, spelling) = match line.split_once('=') {
+        Some((command, spelling)) if !spelling.trim().is_empty() => (command.trim(), spelling.trim()),
+        _ => return Err(invalid(format!("line {} isn't written '<command> = <spelling>'", index + 1)))
+      };
+      let slot = match COMMANDS.iter().position(|(char, _)| command.len() == 1 && command.starts_with(*char)) {
+        None => return Err(invalid(format!("'{}' on line {} isn't a Brainfuck command", command, index + 1))),
+        Some(slot) => slot
+      };
+      if spellings.iter().flatten().any(|other| other == spelling) {
+        return Err(invalid(format!("'{}' is used for more than one command", spelling)));
+      }
+      spellings[slot] = Some(spelling.to_string());
+    }
+    match spellings.iter().position(Option::is_none) {
+      Some(slot) => Err(invalid(format!("there's no spelling for '{}'", COMMANDS[slot].0))),
+      None => Ok(Dialect::new(path, &spellings.map(Option::unwrap)))
+    }
+  }
+
+  // a built-in dialect's name or the path of a dialect file
+  pub fn from_arg(dialect: &str) -> Result<Dialect, Error> {
+    match Dialect::builtin(dialect) {
+      Some(dialect) => Ok(dialect),
+      None => Dialect::load(dialect)
+    }
+  }
+
+  pub fn is_brainfuck(&self) -> bool {
+    self.name == "brainfuck"
+  }
+
+  // whether the dialect has commands beyond the eight of Brainfuck
+  pub fn is_extended(&self) -> bool {
+    self.commands.iter().any(|(_, token)| !COMMANDS.iter().any(|(_, command)| command == token))
+  }
+
+  // Boolfuck runs on a tape of bits, where the commands mean something else than in Brainfuck
+  pub fn has_bit_cells(&self) -> bool {
+    self.name == "boolfuck"
+  }
+
+  // the first command of this dialect that the other one can't spell
+  pub fn missing_from(&self, other: &Dialect) -> Option<String> {
+    self.commands.iter().find(|(_, token)| other.spelling(token).is_empty()).map(|(words, _)| words.join(" "))
+  }
+
+  pub fn spelling(&self, token: &Token) -> String {
+    self.commands.iter().find(|(_, other)| other == token).map_or_else(String::new, |(words, _)| words.join(" "))
+  }
+
+  // commands made of words need a space between them
+  pub fn separator(&self) -> &'static str {
+    if self.commands.iter().any(|(words, _)| words.len() > 1 || words[0].chars().count() > 1) {
+      " "
+    } else {
+      ""
+    }
+  }
+
+  // the command starting at chars[i] and the index right after it
+  pub fn command_at(&self, chars: &[char], i: usize) -> Option<(Token, usize)> {
+    'commands: for (words, token) in &self.commands {
+      let mut end = i;
+      for (index, word) in words.iter().enumerate() {
+        if index > 0 {
+          while end < chars.len() && chars[end].is_whitespace() {
+            end += 1;
+          }
+        }
+        for char in word.chars() {
+          if chars.get(end) != Some(&char) {
+            continue 'commands;
+          }
+          end += 1;
+        }
+      }
+      return Some((token.clone(), end));
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{convert, lex_dialect};
+
+  const PROGRAM: &str = "++[>+<-]>.,\n[-]\n";
+
+  fn tokens(program: &str, dialect: &Dialect) -> Vec<Token> {
+    lex_dialect(program, dialect).into_iter().map(|(token, _)| token).collect()
+  }
+
+  #[test]
+  fn round_trips() {
+    let brainfuck = Dialect::brainfuck();
+    for name in ["ook", "blub", "alphuck", "brainfork", "pbrain", "extended"] {
+      let dialect = Dialect::builtin(name).unwrap();
+      let converted = convert(PROGRAM, &brainfuck, &dialect);
+      assert_eq!(tokens(&converted, &dialect), tokens(PROGRAM, &brainfuck), "{}", name);
+      assert_eq!(convert(&converted, &dialect, &brainfuck), PROGRAM, "{}", name);
+    }
+  }
+
+  #[test]
+  fn extensions_round_trip() {
+    let pbrain = Dialect::builtin("pbrain").unwrap();
+    let ook = Dialect::builtin("ook").unwrap();
+    assert_eq!(convert("(+):", &pbrain, &pbrain), "(+):\n");
+    assert_eq!(pbrain.missing_from(&ook).as_deref(), Some("("));
+    let extended = Dialect::builtin("extended").unwrap();
+    assert_eq!(convert("@$!}{~^&|", &extended, &extended), "@$!}{~^&|\n");
+  }
+
+  #[test]
+  fn words_can_be_split_across_lines() {
+    let ook = Dialect::builtin("ook").unwrap();
+    assert_eq!(tokens("Ook.\n  Ook? Ook!\tOok!", &ook), [Token::MoveRight, Token::Decrement]);
+  }
+
+  #[test]
+  fn missing_commands() {
+    let (brainfuck, boolfuck) = (Dialect::brainfuck(), Dialect::builtin("boolfuck").unwrap());
+    assert_eq!(brainfuck.missing_from(&boolfuck).as_deref(), Some("-"));
+    assert_eq!(Dialect::builtin("brainfork").unwrap().missing_from(&brainfuck).as_deref(), Some("Y"));
+    assert_eq!(brainfuck.missing_from(&Dialect::builtin("alphuck").unwrap()), None);
+  }
+
+  #[test]
+  fn dialect_files() {
+    let path = std::env::temp_dir().join(format!("brainfuck-dialect-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    let load = |content: &str| {
+      fs::write(path, content).unwrap();
+      Dialect::load(path)
+    };
+    let dialect = load("# shouting\n+ = UP\n- = DOWN\n> = RIGHT\n< = LEFT\n. = SAY\n, = HEAR\n[ = WHILE\n] = END\n").unwrap();
+    assert_eq!(convert("+[-]", &Dialect::brainfuck(), &dialect), "UP WHILE DOWN END\n");
+    assert!(matches!(load("+ = UP\n"), Err(Error::InvalidDialect(_, reason)) if reason.contains("'-'")));
+    assert!(matches!(load("+ = UP\n- = UP\n"), Err(Error::InvalidDialect(_, reason)) if reason.contains("more than one")));
+    assert!(matches!(load("x = UP\n"), Err(Error::InvalidDialect(_, reason)) if reason.contains("isn't a Brainfuck command")));
+    assert!(matches!(load("+ UP\n"), Err(Error::InvalidDialect(_, reason)) if reason.contains("line 1")));
+    fs::remove_file(path).unwrap();
+    assert!(matches!(Dialect::from_arg(path), Err(Error::UnknownDialect(_))));
+  }
+}
@@ -0,0 +1,87 @@
+use crate::{Args, Error, Memory};
+use colored::Colorize;
+use std::io::{stderr, Write};
+use std::fs;
+
+const HEX_WIDTH: usize = 16;
+const DECIMAL_WIDTH: usize = 10;
+
+// the cells from the first to the last non-null one, always including the pointer
+fn used<T, M: Memory<T>>(memory: &M) -> (usize, Vec<u32>) {
+  let cells = memory.cells();
+  let pointer = memory.pointer() as usize;
+  let start = cells.iter().position(|&cell| cell != 0).unwrap_or(pointer).min(pointer);
+  let end = cells.iter().rposition(|&cell| cell != 0).unwrap_or(pointer).max(pointer);
+  (start, cells[start..=end].to_vec())
+}
+
+pub fn dump<T, M: Memory<T>>(memory: &M, format: &str) -> Vec<u8> {
+  let bits = M::bits() as usize;
+  let digits = bits.div_ceil(4);
+  let (start, cells) = used(memory);
+  match format {
+    // bits are packed eight to a byte, least significant first, like Boolfuck writes them
+    "raw" if bits < 8 => cells.chunks(8).map(|chunk| {
+      chunk.iter().enumerate().fold(0, |byte, (bit, &cell)| byte | (cell as u8) << bit)
+    }).collect(),
+    "raw" => cells.iter().flat_map(|cell| cell.to_le_bytes()[..bits / 8].to_vec()).collect(),
+    "json" => {
+      let cells: Vec<String> = cells.iter().map(u32::to_string).collect();
+      format!("{{\"cell_size\":{},\"pointer\":{},\"start\":{},\"cells\":[{}]}}\n", bits, memory.pointer(), start, cells.join(",")).into_bytes()
+    }
+    "decimal" => {
+      let width = (u32::MAX >> (32 - bits)).to_string().len();
+      let mut table = String::new();
+      for (row, chunk) in cells.chunks(DECIMAL_WIDTH).enumerate() {
+        let values: Vec<String> = chunk.iter().map(|cell| format!("{:>width$}", cell, width = width)).collect();
+        table += &format!("{:>8}: {}\n", start + row * DECIMAL_WIDTH, values.join(" "));
+      }
+      table.into_bytes()
+    }
+    _ => {
+      let mut table = String::new();
+      for (row, chunk) in cells.chunks(HEX_WIDTH).enumerate() {
+        let values: Vec<String> = chunk.iter().map(|cell| format!("{:0width$x}", cell, width = digits)).collect();
+        let chars: String = chunk.iter().map(|&cell| match char::from_u32(cell) {
+          Some(char) if char.is_ascii_graphic() || char == ' ' => char,
+          _ => '.'
+        }).collect();
+        table += &format!("{:08x}  {:<width$}  |{}|\n", start + row * HEX_WIDTH, values.join(" "), chars, width = HEX_WIDTH * (digits + 1) - 1);
+      }
+      table.into_bytes()
+    }
+  }
+}
+
+// how many cells on each side of the pointer the debugger and the REPL show
+pub const TAPE_RADIUS: u32 = 5;
+
+// the cells within radius of the pointer, with the pointer highlighted
+pub fn tape<T, M: Memory<T>>(memory: &M, radius: u32) -> String {
+  let from = memory.pointer().saturating_sub(radius);
+  let to = memory.pointer().saturating_add(radius).min(memory.size() - 1);
+  let cells: Vec<String> = (from..=to).map(|pointer| {
+    let value = M::value_to_string(memory.value(pointer));
+    if pointer == memory.pointer() {
+      format!("[{}]", value).yellow().to_string()
+    } else {
+      value
+    }
+  }).collect();
+  format!("cells {} to {}: {}", from, to, cells.join(" "))
+}
+
+pub fn write_dump<T, M: Memory<T>>(memory: &M, args: &Args) -> Result<(), Error> {
+  if let Some(format) = &args.dump_memory {
+    let bytes = dump(memory, format);
+    match &args.dump_file {
+      Some(path) => if fs::write(path, bytes).is_err() {
+        return Err(Error::WriteDumpFail(path.clone()));
+      }
+      None => if stderr().write_all(&bytes).is_err() {
+        return Err(Error::WriteDumpFail(String::from("stderr")));
+      }
+    }
+  }
+  Ok(())
+}
@@ -1,10 +1,52 @@
+use crate::Position;
 use colored::Colorize;
 
 #[derive(Debug)]
 pub enum Error {
   // compilation
-  UnmatchedCloseLoop(usize),
-  UnmatchedOpenLoop(usize),
+  UnmatchedCloseLoop(Position),
+  UnmatchedOpenLoop(Position),
+  UnmatchedCloseProcedure(Position),
+  UnmatchedOpenProcedure(Position),
+  InvalidSyntax(Position, String),
+  InvalidDirective(String),
+  IncludeCycle(String),
+  MacroArguments(String, usize, usize, String),
+  MacroRecursion(String, String),
+  Preprocessed(Box<Error>, String),
+  // an error followed by the failure to save or dump the state it left behind
+  Followed(Box<Error>, Box<Error>),
+
+  // setup
+  ReadFileFail(String),
+  ReadProgramFail,
+  ReadInputFileFail(String),
+  ConflictingInput,
+  WriteFileFail(String),
+  LoadStateFail(String),
+  SaveStateFail(String),
+  InvalidState(String),
+  UnsupportedStateVersion(u32),
+  ProgramChanged(String),
+  CreateTraceFail(String),
+  ReadInitMemoryFail(String),
+  InvalidInitValue(String),
+  InitMemoryTooLarge(usize, u32),
+  InitValueTooLarge(u32, u32, u32),
+  InitPointerOutOfBounds(u32, u32),
+  InvalidTimeout(f64),
+  MemoryLimitExceeded(u64, u64),
+  UnknownDialect(String),
+  InvalidDialect(String, String),
+  UnsupportedDialect(String, &'static str),
+  UnsupportedOption(&'static str, &'static str),
+  MissingCommand(String, String),
+  FormatChangedProgram,
+  Unformatted(usize),
+  MinifyMismatch,
+  UnknownLint(String),
+  LintWarnings,
+  GeneratedTextMismatch,
 
   // runtime
   PositiveOverflow(u32),
@@ -16,24 +58,129 @@ pub enum Error {
   NoInput,
   ReadInputFail,
   Requires32Bits(char),
-  Requires16Bits(char)
+  Requires16Bits(char),
+  WriteTraceFail,
+  WriteDumpFail(String),
+  StepLimitReached(u64),
+  TimedOut(f64, u64),
+  OutputLimitReached(u64),
+  InfiniteLoop(Position),
+  TooManyThreads(usize),
+  UndefinedProcedure(u32, Position),
+  StackOverflow(usize),
+  // not a failure, @ ends the program by unwinding through it
+  ProgramEnded
 }
 
 impl Error {
+  pub fn label(&self) -> &'static str {
+    match self {
+      Error::UnmatchedCloseLoop(_)
+      | Error::UnmatchedOpenLoop(_)
+      | Error::UnmatchedCloseProcedure(_)
+      | Error::UnmatchedOpenProcedure(_)
+      | Error::InvalidSyntax(_, _)
+      | Error::InvalidDirective(_)
+      | Error::IncludeCycle(_)
+      | Error::MacroArguments(_, _, _, _)
+      | Error::MacroRecursion(_, _) => "compilation error:",
+      Error::Preprocessed(err, _) => err.label(),
+      Error::Followed(err, _) => err.label(),
+      Error::ReadFileFail(_)
+      | Error::ReadProgramFail
+      | Error::ReadInputFileFail(_)
+      | Error::ConflictingInput
+      | Error::WriteFileFail(_)
+      | Error::LoadStateFail(_)
+      | Error::SaveStateFail(_)
+      | Error::InvalidState(_)
+      | Error::UnsupportedStateVersion(_)
+      | Error::ProgramChanged(_)
+      | Error::CreateTraceFail(_)
+      | Error::ReadInitMemoryFail(_)
+      | Error::InvalidInitValue(_)
+      | Error::InitMemoryTooLarge(_, _)
+      | Error::InitValueTooLarge(_, _, _)
+      | Error::InitPointerOutOfBounds(_, _)
+      | Error::InvalidTimeout(_)
+      | Error::MemoryLimitExceeded(_, _)
+      | Error::UnknownDialect(_)
+      | Error::InvalidDialect(_, _)
+      | Error::UnsupportedDialect(_, _)
+      | Error::UnsupportedOption(_, _)
+      | Error::MissingCommand(_, _)
+      | Error::FormatChangedProgram
+      | Error::Unformatted(_)
+      | Error::MinifyMismatch
+      | Error::UnknownLint(_)
+      | Error::LintWarnings
+      | Error::GeneratedTextMismatch => "error:",
+      _ => "\nruntime error:"
+    }
+  }
+
   pub fn message(&self) -> String {
     match self {
-      Error::UnmatchedCloseLoop(position) => String::from(format!("Unmatched close loop token at position {}", position.to_string().green())),
-      Error::UnmatchedOpenLoop(position) => String::from(format!("Unmatched open loop token at position {}", position.to_string().green())),
-      Error::PositiveOverflow(pointer) => String::from(format!("Cell {} positively overflowed", pointer.to_string().green())),
-      Error::NegativeOverflow(pointer) => String::from(format!("Cell {} negatively overflowed", pointer.to_string().green())),
+      Error::UnmatchedCloseLoop(position) => format!("Unmatched close loop token at position {}", position.to_string().green()),
+      Error::UnmatchedOpenLoop(position) => format!("Unmatched open loop token at position {}", position.to_string().green()),
+      Error::UnmatchedCloseProcedure(position) => format!("Unmatched procedure end token at position {}", position.to_string().green()),
+      Error::UnmatchedOpenProcedure(position) => format!("Unmatched procedure start token at position {}", position.to_string().green()),
+      Error::InvalidSyntax(position, message) => format!("{} at position {}", message, position.to_string().green()),
+      Error::InvalidDirective(location) => format!("Invalid preprocessor directive in {}", location.yellow()),
+      Error::IncludeCycle(chain) => format!("Files include each other in a cycle: {}", chain.yellow()),
+      Error::MacroArguments(name, expected, given, location) => format!("The macro {} takes {} arguments but was given {} in {}", name.yellow(), expected.to_string().green(), given.to_string().green(), location.yellow()),
+      Error::MacroRecursion(name, location) => format!("The macro {} expands itself forever in {}", name.yellow(), location.yellow()),
+      Error::Preprocessed(err, location) => format!("{} ({})", err.message(), location.yellow()),
+      Error::Followed(err, failure) => format!("{}\n{} {}", err.message(), failure.label().trim_start().red(), failure.message()),
+      Error::ReadFileFail(path) => format!("Couldn't read the file {}, are you sure the path is valid?", path.yellow()),
+      Error::ReadProgramFail => String::from("Couldn't read the program from stdin"),
+      Error::ReadInputFileFail(path) => format!("Couldn't read the input file {}", path.yellow()),
+      Error::ConflictingInput => String::from("The program's input was given more than once"),
+      Error::WriteFileFail(path) => format!("Couldn't write the file {}", path.yellow()),
+      Error::LoadStateFail(path) => format!("Couldn't read the state file {}", path.yellow()),
+      Error::SaveStateFail(path) => format!("Couldn't write the state file {}", path.yellow()),
+      Error::InvalidState(path) => format!("{} isn't a valid state file for this program", path.yellow()),
+      Error::UnsupportedStateVersion(version) => format!("Unsupported state file version {}", version.to_string().green()),
+      Error::ProgramChanged(path) => format!("{} changed since the state was saved", path.yellow()),
+      Error::CreateTraceFail(path) => format!("Couldn't create the trace file {}", path.yellow()),
+      Error::ReadInitMemoryFail(path) => format!("Couldn't read the initial memory file {}", path.yellow()),
+      Error::InvalidInitValue(value) => format!("'{}' isn't a valid initial cell value", value.yellow()),
+      Error::InitMemoryTooLarge(cells, size) => format!("The initial memory has {} cells but memory only has {}", cells.to_string().green(), size.to_string().green()),
+      Error::InitValueTooLarge(pointer, value, bits) => format!("Cell {} can't hold the value {} in {} bits", pointer.to_string().green(), value.to_string().green(), bits.to_string().green()),
+      Error::InitPointerOutOfBounds(pointer, size) => format!("The initial pointer {} is outside of memory, which has {} cells", pointer.to_string().green(), size.to_string().green()),
+      Error::InvalidTimeout(seconds) => format!("{} isn't a valid timeout", seconds.to_string().green()),
+      Error::MemoryLimitExceeded(bytes, max_memory) => format!("Allocating {} bytes of memory exceeds the limit of {} bytes", bytes.to_string().green(), max_memory.to_string().green()),
+      Error::UnknownDialect(dialect) => format!("{} isn't a built-in dialect or a dialect file that could be read", dialect.yellow()),
+      Error::InvalidDialect(path, reason) => format!("The dialect file {} is invalid, {}", path.yellow(), reason),
+      Error::UnsupportedDialect(dialect, feature) => format!("The {} dialect can't be used with {}", dialect.yellow(), feature),
+      Error::UnsupportedOption(option, feature) => format!("The {} option can't be used with {}", option.yellow(), feature),
+      Error::MissingCommand(dialect, command) => format!("The {} dialect has no command for '{}'", dialect.yellow(), command.yellow()),
+      Error::FormatChangedProgram => String::from("Formatting would change the program, please report this as a bug"),
+      Error::Unformatted(files) => format!("Found {} unformatted files, run 'brainfuck fmt' on them", files.to_string().green()),
+      Error::MinifyMismatch => String::from("The minified program behaves differently from the original on the sample input"),
+      Error::UnknownLint(lint) => format!("'{}' isn't a lint code or name", lint.yellow()),
+      Error::LintWarnings => String::from("Found warnings while warnings are denied"),
+      Error::GeneratedTextMismatch => String::from("The generated program doesn't print the text, please report this as a bug"),
+      Error::PositiveOverflow(pointer) => format!("Cell {} positively overflowed", pointer.to_string().green()),
+      Error::NegativeOverflow(pointer) => format!("Cell {} negatively overflowed", pointer.to_string().green()),
       Error::RightMostCell => String::from("Reached the rightmost cell"),
       Error::LeftMostCell => String::from("Reached the leftmost cell"),
-      Error::InvalidUnicode(value) => String::from(format!("{} isn't a valid Unicode scalar value", value.to_string().green())),
+      Error::InvalidUnicode(value) => format!("{} isn't a valid Unicode scalar value", value.to_string().green()),
       Error::WriteOutputFail => String::from("Failed to write output"),
       Error::NoInput => String::from("Expected input, got none"),
       Error::ReadInputFail => String::from("Failed to read input"),
-      Error::Requires32Bits(char) => String::from(format!("Storing the character '{}' requires 32 bits", char.to_string().yellow())),
-      Error::Requires16Bits(char) => String::from(format!("Storing the character '{}' requires 16 bits", char.to_string().yellow())),
+      Error::Requires32Bits(char) => format!("Storing the character '{}' requires 32 bits", char.to_string().yellow()),
+      Error::Requires16Bits(char) => format!("Storing the character '{}' requires 16 bits", char.to_string().yellow()),
+      Error::WriteTraceFail => String::from("Failed to write to the trace file"),
+      Error::WriteDumpFail(path) => format!("Failed to write the memory dump to {}", path.yellow()),
+      Error::StepLimitReached(steps) => format!("Reached the step limit after {} steps", steps.to_string().green()),
+      Error::TimedOut(seconds, steps) => format!("Timed out after {}s and {} steps", seconds.to_string().green(), steps.to_string().green()),
+      Error::OutputLimitReached(max_output) => format!("Reached the output limit of {} bytes", max_output.to_string().green()),
+      Error::InfiniteLoop(position) => format!("The loop at position {} never terminates", position.to_string().green()),
+      Error::TooManyThreads(max_threads) => format!("Forked more than {} threads", max_threads.to_string().green()),
+      Error::UndefinedProcedure(number, position) => format!("Called procedure {} at position {} before it was defined", number.to_string().green(), position.to_string().green()),
+      Error::StackOverflow(max_depth) => format!("Procedure calls nested deeper than {}", max_depth.to_string().green()),
+      Error::ProgramEnded => String::from("The program ended"),
     }
   }
 }
\ No newline at end of file
@@ -0,0 +1,157 @@
+use crate::{lex, lex_with_comments, parse, Error, Position, Token};
+
+const INDENT: &str = "  ";
+const MAX_INLINE_LOOP: usize = 16;
+
+// a loop without nested loops or comments that is short enough to stay on one line,
+// returns its source and the index of its closing bracket
+fn inline_loop(tokens: &[(Token, Position)], open: usize) -> Option<(String, usize)> {
+  let mut code = String::from("[");
+  for (i, (token, _)) in tokens.iter().enumerate().skip(open + 1) {
+    match token {
+      Token::EnterLoop => return None,
+      Token::ExitLoop => {
+        code.push(']');
+        return Some((code, i));
+      }
+      Token::Comment(text) => if !text.trim().is_empty() {
+        return None;
+      }
+      token => {
+        code.push(token.to_char()?);
+        if code.len() > MAX_INLINE_LOOP + 1 {
+          return None;
+        }
+      }
+    }
+  }
+  None
+}
+
+#[derive(Default)]
+struct Formatter {
+  lines: Vec<String>,
+  line: String,
+  indent: usize,
+  last: Option<char>,
+  closed: bool
+}
+
+impl Formatter {
+  fn push(&mut self, text: &str, separate: bool) {
+    if self.line.is_empty() {
+      self.line = INDENT.repeat(self.indent);
+    } else if separate {
+      self.line.push(' ');
+    }
+    self.line += text;
+  }
+
+  fn newline(&mut self) {
+    if !self.line.is_empty() {
+      self.lines.push(std::mem::take(&mut self.line));
+    }
+    self.last = None;
+    self.closed = false;
+  }
+
+  fn blank_line(&mut self) {
+    self.newline();
+    if self.lines.last().is_some_and(|line| !line.is_empty()) {
+      self.lines.push(String::new());
+    }
+  }
+
+  // runs of the same command stick together, different runs are separated by a space
+  fn code(&mut self, code: &str, run: Option<char>) {
+    if self.closed {
+      self.newline();
+    }
+    let separate = run.is_none() || self.last != run;
+    self.push(code, separate);
+    self.last = run;
+  }
+
+  fn open(&mut self) {
+    self.newline();
+    self.push("[", false);
+    self.indent += 1;
+    self.closed = true;
+  }
+
+  fn close(&mut self) {
+    self.newline();
+    self.indent -= 1;
+    self.push("]", false);
+    self.closed = true;
+  }
+
+  // text on the same line as code trails it, text on its own line stays on its own line
+  // before the code that follows, and blank lines are kept but collapsed
+  fn comment(&mut self, text: &str) {
+    let mut lines = text.split('\n').peekable();
+    let mut first = true;
+    while let Some(line) = lines.next() {
+      let line = line.trim();
+      if !first {
+        if line.is_empty() && lines.peek().is_some() {
+          self.blank_line();
+        } else {
+          self.newline();
+        }
+      }
+      if !line.is_empty() {
+        self.push(line, true);
+        self.last = None;
+      }
+      first = false;
+    }
+  }
+
+  fn finish(mut self) -> String {
+    self.newline();
+    while self.lines.last().is_some_and(String::is_empty) {
+      self.lines.pop();
+    }
+    while self.lines.first().is_some_and(String::is_empty) {
+      self.lines.remove(0);
+    }
+    let mut source = self.lines.join("\n");
+    if !source.is_empty() {
+      source.push('\n');
+    }
+    source
+  }
+}
+
+pub fn format_source(source: &str) -> Result<String, Error> {
+  parse(&lex(source))?;
+  let tokens = lex_with_comments(source);
+  let mut formatter = Formatter::default();
+  let mut i = 0;
+  while i < tokens.len() {
+    match &tokens[i].0 {
+      Token::Comment(text) => formatter.comment(text),
+      Token::EnterLoop => match inline_loop(&tokens, i) {
+        Some((code, end)) => {
+          formatter.code(&code, None);
+          i = end;
+        }
+        None => formatter.open()
+      }
+      Token::ExitLoop => formatter.close(),
+      token => {
+        let char = token.to_char().unwrap();
+        formatter.code(&char.to_string(), Some(char));
+      }
+    }
+    i += 1;
+  }
+  let formatted = formatter.finish();
+  let before: Vec<Token> = lex(source).into_iter().map(|(token, _)| token).collect();
+  let after: Vec<Token> = lex(&formatted).into_iter().map(|(token, _)| token).collect();
+  if before != after {
+    return Err(Error::FormatChangedProgram);
+  }
+  Ok(formatted)
+}
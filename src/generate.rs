@@ -0,0 +1,116 @@
+pub const STRATEGIES: &[&str] = &["best", "naive", "loops", "cells"];
+
+const MAX_BASE: u32 = 24;
+
+fn edit(difference: i64) -> String {
+  if difference >= 0 {
+    "+".repeat(difference as usize)
+  } else {
+    "-".repeat(-difference as usize)
+  }
+}
+
+fn moves(from: usize, to: usize) -> String {
+  if to >= from {
+    ">".repeat(to - from)
+  } else {
+    "<".repeat(from - to)
+  }
+}
+
+// changes the current cell by difference, with a multiplication loop on the next cell
+// when that's shorter, the next cell has to be null and is left null
+fn multiply(difference: i64) -> String {
+  let mut best = (difference.abs(), 1, difference);
+  for times in 2..=difference.abs() {
+    let factor = (difference as f64 / times as f64).round() as i64;
+    if factor == 0 {
+      break;
+    }
+    let rest = difference - times * factor;
+    let length = times + factor.abs() + rest.abs() + 6;
+    if length < best.0 {
+      best = (length, times, factor);
+    }
+  }
+  match best {
+    (_, 1, _) => edit(difference),
+    (_, times, factor) => format!(">{}[<{}>-]<{}", edit(times), edit(factor), edit(difference - times * factor))
+  }
+}
+
+// every character is reached from the previous one on a single cell
+fn naive(text: &[u32]) -> String {
+  let mut bf = String::new();
+  let mut current = 0;
+  for &char in text {
+    bf += &edit(char as i64 - current);
+    bf.push('.');
+    current = char as i64;
+  }
+  bf
+}
+
+// like naive, but large differences use a multiplication loop
+fn loops(text: &[u32]) -> String {
+  let mut bf = String::new();
+  let mut current = 0;
+  for &char in text {
+    bf += &multiply(char as i64 - current);
+    bf.push('.');
+    current = char as i64;
+  }
+  bf
+}
+
+// a single loop fills several cells with multiples of a base close to the characters,
+// then each character is printed from whichever cell is cheapest to reach and adjust
+fn cells_with_base(text: &[u32], base: u32) -> String {
+  let max = text.iter().copied().max().unwrap_or(0);
+  let mut factors: Vec<u32> = text.iter().map(|&char| {
+    let factor = (char + base / 2) / base;
+    if factor * base > max { factor - 1 } else { factor }
+  }).filter(|&factor| factor > 0).collect();
+  factors.sort_unstable();
+  factors.dedup();
+  let mut bf = String::new();
+  // cell 0 counts down, the others hold the values
+  let mut values = vec![0i64];
+  if !factors.is_empty() {
+    bf += &edit(base as i64);
+    bf.push('[');
+    for factor in &factors {
+      bf.push('>');
+      bf += &edit(*factor as i64);
+      values.push((factor * base) as i64);
+    }
+    bf += &moves(factors.len(), 0);
+    bf += "-]";
+  }
+  let mut pointer = 0;
+  for &char in text {
+    let char = char as i64;
+    let cost = |cell: usize| (cell as i64 - pointer as i64).abs() + (values[cell] - char).abs();
+    let cell = (0..values.len()).min_by_key(|&cell| cost(cell)).unwrap();
+    bf += &moves(pointer, cell);
+    bf += &edit(char - values[cell]);
+    bf.push('.');
+    values[cell] = char;
+    pointer = cell;
+  }
+  bf
+}
+
+fn cells(text: &[u32]) -> String {
+  (2..=MAX_BASE).map(|base| cells_with_base(text, base)).min_by_key(String::len).unwrap()
+}
+
+pub fn generate_text(text: &str, strategy: &str) -> String {
+  let text: Vec<u32> = text.chars().map(|char| char as u32).collect();
+  match strategy {
+    "naive" => naive(&text),
+    "loops" => loops(&text),
+    "cells" => cells(&text),
+    _ => vec![naive(&text), loops(&text), cells(&text)].into_iter().min_by_key(String::len).unwrap()
+  }
+}
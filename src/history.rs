@@ -0,0 +1,89 @@
+const SNAPSHOT_INTERVAL: usize = 10000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Change {
+  Pointer(u32),
+  Cell(u32, u32)
+}
+
+// everything needed to undo a single debugger step
+#[derive(Debug)]
+pub struct Record {
+  pub steps: u64,
+  pub path: Vec<usize>,
+  pub changes: Vec<Change>
+}
+
+// a full copy of the tape taken before the record at `index` was executed
+#[derive(Debug)]
+pub struct Snapshot {
+  pub index: usize,
+  pub steps: u64,
+  pub path: Vec<usize>,
+  pub pointer: u32,
+  pub cells: Vec<u32>
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+  records: Vec<Record>,
+  snapshots: Vec<Snapshot>
+}
+
+impl History {
+  pub fn len(&self) -> usize {
+    self.records.len()
+  }
+
+  // how many records to keep to get back to `steps` or just before, records start at ascending steps
+  // and a loop entered takes two steps, so there isn't always a record starting at exactly `steps`
+  pub fn kept_at(&self, steps: u64) -> usize {
+    self.records.partition_point(|record| record.steps <= steps).saturating_sub(1)
+  }
+
+  pub fn wants_snapshot(&self) -> bool {
+    self.records.len().is_multiple_of(SNAPSHOT_INTERVAL)
+      && self.snapshots.last().is_none_or(|snapshot| snapshot.index != self.records.len())
+  }
+
+  pub fn snapshot(&mut self, steps: u64, path: Vec<usize>, pointer: u32, cells: Vec<u32>) {
+    self.snapshots.push(Snapshot { index: self.records.len(), steps, path, pointer, cells });
+  }
+
+  pub fn begin(&mut self, steps: u64, path: Vec<usize>) {
+    self.records.push(Record { steps, path, changes: vec![] });
+  }
+
+  pub fn record(&mut self, change: Change) {
+    if let Some(record) = self.records.last_mut() {
+      record.changes.push(change);
+    }
+  }
+
+  pub fn pop(&mut self) -> Option<Record> {
+    let record = self.records.pop();
+    while self.snapshots.last().is_some_and(|snapshot| snapshot.index > self.records.len()) {
+      self.snapshots.pop();
+    }
+    record
+  }
+
+  // the oldest snapshot that lets us jump back to the record at `index`
+  // without undoing every record in between
+  pub fn rewind(&mut self, index: usize) -> Option<Snapshot> {
+    let position = self.snapshots.iter().position(|snapshot| snapshot.index >= index)?;
+    if self.snapshots[position].index >= self.records.len() {
+      return None;
+    }
+    self.snapshots.truncate(position + 1);
+    let snapshot = self.snapshots.pop()?;
+    self.records.truncate(snapshot.index);
+    Some(snapshot)
+  }
+
+  pub fn last_write(&self, pointer: u32) -> Option<&Record> {
+    self.records.iter().rev().find(|record| {
+      record.changes.iter().any(|change| matches!(change, Change::Cell(cell, _) if *cell == pointer))
+    })
+  }
+}
@@ -1,4 +1,5 @@
-use crate::{Memory, Token, Error};
+use crate::{Memory, Token, Position, Error};
+use std::fmt;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Edit {
@@ -12,70 +13,216 @@ pub enum Move {
   Left(u32)
 }
 
+// the operations of Extended Brainfuck Type I, the binary ones take the register as their other operand
+#[derive(Debug, Clone, Copy)]
+pub enum Bitwise {
+  ShiftRight,
+  ShiftLeft,
+  Not,
+  Xor,
+  And,
+  Or
+}
+
+impl fmt::Display for Edit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Edit::Increment(n) => write!(f, "+{}", n),
+      Edit::Decrement(n) => write!(f, "-{}", n)
+    }
+  }
+}
+
+impl fmt::Display for Bitwise {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Bitwise::ShiftRight => write!(f, "}}"),
+      Bitwise::ShiftLeft => write!(f, "{{"),
+      Bitwise::Not => write!(f, "~"),
+      Bitwise::Xor => write!(f, "^"),
+      Bitwise::And => write!(f, "&"),
+      Bitwise::Or => write!(f, "|")
+    }
+  }
+}
+
+impl fmt::Display for Move {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Move::Right(n) => write!(f, ">{}", n),
+      Move::Left(n) => write!(f, "<{}", n)
+    }
+  }
+}
+
+// every instruction keeps the position of the command it starts at, so traces
+// and the debugger can point back at the source
 #[derive(Debug)]
 pub enum Instruction {
-  Loop(Vec<Instruction>),
-  EditOtherLoop(Move, Edit),
-  MoveEdit(Move, Edit),
-  EditOther(Move, Edit),
-  Edit(Edit),
-  Move(Move),
-  Clear,
-  Write,
-  Read,
+  Loop(Vec<Instruction>, Position),
+  InfiniteLoop(Vec<Instruction>, Position),
+  #[allow(dead_code)]
+  EditOtherLoop(Move, Edit, Position),
+  MoveEdit(Move, Edit, Position),
+  EditOther(Move, Edit, Position),
+  Edit(Edit, Position),
+  Move(Move, Position),
+  Clear(Position),
+  Write(Position),
+  Read(Position),
+  Fork(Position),
+  Procedure(Vec<Instruction>, Position),
+  Call(Position),
+  End(Position),
+  Store(Position),
+  Load(Position),
+  Bitwise(Bitwise, Position)
 }
 
 impl Instruction {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Instruction::Loop(_, _) => "loop",
+      Instruction::InfiniteLoop(_, _) => "infinite-loop",
+      Instruction::EditOtherLoop(_, _, _) => "edit-other-loop",
+      Instruction::MoveEdit(_, _, _) => "move-edit",
+      Instruction::EditOther(_, _, _) => "edit-other",
+      Instruction::Edit(_, _) => "edit",
+      Instruction::Move(_, _) => "move",
+      Instruction::Clear(_) => "clear",
+      Instruction::Write(_) => "write",
+      Instruction::Read(_) => "read",
+      Instruction::Fork(_) => "fork",
+      Instruction::Procedure(_, _) => "procedure",
+      Instruction::Call(_) => "call",
+      Instruction::End(_) => "end",
+      Instruction::Store(_) => "store",
+      Instruction::Load(_) => "load",
+      Instruction::Bitwise(_, _) => "bitwise"
+    }
+  }
+
+  pub fn position(&self) -> Position {
+    match self {
+      Instruction::Loop(_, position)
+      | Instruction::InfiniteLoop(_, position)
+      | Instruction::EditOtherLoop(_, _, position)
+      | Instruction::MoveEdit(_, _, position)
+      | Instruction::EditOther(_, _, position)
+      | Instruction::Edit(_, position)
+      | Instruction::Move(_, position)
+      | Instruction::Clear(position)
+      | Instruction::Write(position)
+      | Instruction::Read(position)
+      | Instruction::Fork(position)
+      | Instruction::Procedure(_, position)
+      | Instruction::Call(position)
+      | Instruction::End(position)
+      | Instruction::Store(position)
+      | Instruction::Load(position)
+      | Instruction::Bitwise(_, position) => *position
+    }
+  }
+
   pub fn run<T, M: Memory<T>>(&self, memory: &mut M) -> Result<(), Error> {
-    //memory.write()?;
-    //println!("{:?}", self);
+    memory.step(self)?;
     match self {
-      &Instruction::Edit(Edit::Increment(n)) => memory.incr_current(n),
-      &Instruction::Edit(Edit::Decrement(n)) => memory.decr_current(n),
-      &Instruction::Move(Move::Right(n)) => memory.incr_pointer(n),
-      &Instruction::Move(Move::Left(n)) => memory.decr_pointer(n),
-      Instruction::Clear => memory.clear_current(),
-      Instruction::Write => memory.write(),
-      Instruction::Read => memory.read(),
-      Instruction::Loop(instructions) => {
+      Instruction::Edit(Edit::Increment(n), _) => memory.incr_current(*n),
+      Instruction::Edit(Edit::Decrement(n), _) => memory.decr_current(*n),
+      Instruction::Move(Move::Right(n), _) => memory.incr_pointer(*n),
+      Instruction::Move(Move::Left(n), _) => memory.decr_pointer(*n),
+      Instruction::Clear(_) => memory.clear_current(),
+      Instruction::Write(_) => memory.write(),
+      Instruction::Read(_) => memory.read(),
+      // the parent's side of a fork, the scheduler starts the child
+      Instruction::Fork(_) => memory.clear_current(),
+      // unwinds every loop, the interpreter then stops as if the program was over
+      Instruction::End(_) => Err(Error::ProgramEnded),
+      Instruction::Store(_) => {
+        let value = M::value_to_u32(memory.current());
+        memory.runtime_mut().register = value;
+        Ok(())
+      }
+      Instruction::Load(_) => {
+        let value = M::value_from_u32(memory.runtime().register);
+        memory.set_value(memory.pointer(), value);
+        Ok(())
+      }
+      // converting the result back to a cell drops the bits that don't fit in it
+      Instruction::Bitwise(bitwise, _) => {
+        let (value, register) = (M::value_to_u32(memory.current()), memory.runtime().register);
+        let value = match bitwise {
+          Bitwise::ShiftRight => value >> 1,
+          Bitwise::ShiftLeft => value << 1,
+          Bitwise::Not => !value,
+          Bitwise::Xor => value ^ register,
+          Bitwise::And => value & register,
+          Bitwise::Or => value | register
+        };
+        memory.set_value(memory.pointer(), M::value_from_u32(value));
+        Ok(())
+      }
+      Instruction::Procedure(_, _)
+      | Instruction::Call(_) => unreachable!("procedures are run by the debugger, which knows where they are"),
+      Instruction::Loop(instructions, position) => {
         while !memory.current_is_null() {
+          memory.iterate(*position)?;
+          for instruction in instructions {
+            instruction.run(memory)?;
+          }
+        }
+        Ok(())
+      }
+      Instruction::InfiniteLoop(instructions, position) => {
+        if !memory.current_is_null() {
+          let value = M::value_to_u32(memory.current());
+          memory.iterate(*position)?;
           for instruction in instructions {
             instruction.run(memory)?;
           }
+          if M::value_to_u32(memory.current()) == value {
+            return Err(Error::InfiniteLoop(*position));
+          }
+          while !memory.current_is_null() {
+            memory.iterate(*position)?;
+            for instruction in instructions {
+              instruction.run(memory)?;
+            }
+          }
         }
         Ok(())
       }
-      Instruction::EditOtherLoop(mov, edit) => {
-        let pointer = match mov {
-          &Move::Right(n) => memory.add_pointer(n)?,
-          &Move::Left(n) => memory.sub_pointer(n)?
+      Instruction::EditOtherLoop(mov, edit, _) => {
+        let pointer = match *mov {
+          Move::Right(n) => memory.add_pointer(n)?,
+          Move::Left(n) => memory.sub_pointer(n)?
         };
-        match edit {
-          &Edit::Increment(n) => memory.incr_value(pointer, n * M::value_to_u32(memory.current()))?,
-          &Edit::Decrement(n) => memory.decr_value(pointer, n * M::value_to_u32(memory.current()))?
+        match *edit {
+          Edit::Increment(n) => memory.incr_value(pointer, n * M::value_to_u32(memory.current()))?,
+          Edit::Decrement(n) => memory.decr_value(pointer, n * M::value_to_u32(memory.current()))?
         }
         memory.clear_current()?;
         Ok(())
       }
-      Instruction::MoveEdit(mov, edit) => {
-        match mov {
-          &Move::Right(n) => memory.incr_pointer(n)?,
-          &Move::Left(n) => memory.decr_pointer(n)?
+      Instruction::MoveEdit(mov, edit, _) => {
+        match *mov {
+          Move::Right(n) => memory.incr_pointer(n)?,
+          Move::Left(n) => memory.decr_pointer(n)?
         }
-        match edit {
-          &Edit::Increment(n) => memory.incr_current(n)?,
-          &Edit::Decrement(n) => memory.decr_current(n)?
+        match *edit {
+          Edit::Increment(n) => memory.incr_current(n)?,
+          Edit::Decrement(n) => memory.decr_current(n)?
         }
         Ok(())
       }
-      Instruction::EditOther(mov, edit) => {
-        let pointer = match mov {
-          &Move::Right(n) => memory.add_pointer(n)?,
-          &Move::Left(n) => memory.sub_pointer(n)?
+      Instruction::EditOther(mov, edit, _) => {
+        let pointer = match *mov {
+          Move::Right(n) => memory.add_pointer(n)?,
+          Move::Left(n) => memory.sub_pointer(n)?
         };
-        match edit {
-          &Edit::Increment(n) => memory.incr_value(pointer, n)?,
-          &Edit::Decrement(n) => memory.decr_value(pointer, n)?
+        match *edit {
+          Edit::Increment(n) => memory.incr_value(pointer, n)?,
+          Edit::Decrement(n) => memory.decr_value(pointer, n)?
         }
         Ok(())
       }
@@ -83,35 +230,75 @@ impl Instruction {
   }
 }
 
-pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Instruction>, Error> {
-  parse_inner(tokens, false, &mut 0)
+impl fmt::Display for Instruction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Instruction::EditOtherLoop(mov, edit, _)
+      | Instruction::MoveEdit(mov, edit, _)
+      | Instruction::EditOther(mov, edit, _) => write!(f, "{} {} {}", self.name(), mov, edit),
+      Instruction::Edit(edit, _) => write!(f, "{} {}", self.name(), edit),
+      Instruction::Move(mov, _) => write!(f, "{} {}", self.name(), mov),
+      Instruction::Bitwise(bitwise, _) => write!(f, "{} {}", self.name(), bitwise),
+      _ => write!(f, "{}", self.name())
+    }
+  }
 }
 
-fn parse_inner(tokens: &Vec<Token>, is_loop: bool, i: &mut usize) -> Result<Vec<Instruction>, Error> {
+pub fn parse(tokens: &[(Token, Position)]) -> Result<Vec<Instruction>, Error> {
+  parse_inner(tokens, None, &mut 0)
+}
+
+// open is the token of the loop or procedure being parsed
+fn parse_inner(tokens: &[(Token, Position)], open: Option<&(Token, Position)>, i: &mut usize) -> Result<Vec<Instruction>, Error> {
   let mut instructions = vec![];
   while *i < tokens.len() {
-    instructions.push(match tokens[*i] {
-      Token::Increment => Instruction::Edit(Edit::Increment(1)),
-      Token::Decrement => Instruction::Edit(Edit::Decrement(1)),
-      Token::MoveRight => Instruction::Move(Move::Right(1)),
-      Token::MoveLeft => Instruction::Move(Move::Left(1)),
-      Token::Write => Instruction::Write,
-      Token::Read => Instruction::Read,
+    let (token, position) = &tokens[*i];
+    instructions.push(match token {
+      Token::Increment => Instruction::Edit(Edit::Increment(1), *position),
+      Token::Decrement => Instruction::Edit(Edit::Decrement(1), *position),
+      Token::MoveRight => Instruction::Move(Move::Right(1), *position),
+      Token::MoveLeft => Instruction::Move(Move::Left(1), *position),
+      Token::Write => Instruction::Write(*position),
+      Token::Read => Instruction::Read(*position),
+      Token::Fork => Instruction::Fork(*position),
+      Token::Call => Instruction::Call(*position),
+      Token::End => Instruction::End(*position),
+      Token::Store => Instruction::Store(*position),
+      Token::Load => Instruction::Load(*position),
+      Token::ShiftRight => Instruction::Bitwise(Bitwise::ShiftRight, *position),
+      Token::ShiftLeft => Instruction::Bitwise(Bitwise::ShiftLeft, *position),
+      Token::Not => Instruction::Bitwise(Bitwise::Not, *position),
+      Token::Xor => Instruction::Bitwise(Bitwise::Xor, *position),
+      Token::And => Instruction::Bitwise(Bitwise::And, *position),
+      Token::Or => Instruction::Bitwise(Bitwise::Or, *position),
       Token::EnterLoop => {
         *i += 1;
-        Instruction::Loop(parse_inner(tokens, true, i)?)
+        Instruction::Loop(parse_inner(tokens, Some(&tokens[*i - 1]), i)?, *position)
+      }
+      Token::StartProcedure => {
+        *i += 1;
+        Instruction::Procedure(parse_inner(tokens, Some(&tokens[*i - 1]), i)?, *position)
       }
-      Token::ExitLoop => if is_loop {
+      Token::ExitLoop => if let Some((Token::EnterLoop, _)) = open {
         return Ok(instructions);
       } else {
-        return Err(Error::UnmatchedCloseLoop(*i));
+        return Err(Error::UnmatchedCloseLoop(*position));
+      }
+      Token::EndProcedure => if let Some((Token::StartProcedure, _)) = open {
+        return Ok(instructions);
+      } else {
+        return Err(Error::UnmatchedCloseProcedure(*position));
+      }
+      Token::Comment(_) => {
+        *i += 1;
+        continue;
       }
     });
     *i += 1;
   }
-  if is_loop {
-    Err(Error::UnmatchedOpenLoop(*i))
-  } else {
-    Ok(instructions)
+  match open {
+    Some((Token::StartProcedure, position)) => Err(Error::UnmatchedOpenProcedure(*position)),
+    Some((_, position)) => Err(Error::UnmatchedOpenLoop(*position)),
+    None => Ok(instructions)
   }
-}
\ No newline at end of file
+}
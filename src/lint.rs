@@ -0,0 +1,188 @@
+use crate::{lex, lex_with_comments, optimise, parse, Error, Instruction, Position, Token};
+use std::collections::{HashMap, HashSet};
+use colored::Colorize;
+use std::fmt;
+
+// names use underscores since dashes are Brainfuck commands and couldn't be written in a comment
+const LINTS: &[(&str, &str)] = &[
+  ("W001", "dead_start_loop"),
+  ("W002", "double_clear"),
+  ("W003", "cancelling_commands"),
+  ("W004", "infinite_loop"),
+  ("W005", "dead_loop"),
+  ("W006", "command_in_comment")
+];
+
+#[derive(Debug)]
+pub struct Warning {
+  pub code: &'static str,
+  pub position: Position,
+  pub message: String
+}
+
+impl fmt::Display for Warning {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {}", format!("warning[{}]:", self.code).yellow(), self.message)
+  }
+}
+
+// a code like W003 or a name like cancelling_commands
+pub fn lint_code(lint: &str) -> Option<&'static str> {
+  LINTS.iter().find(|(code, name)| lint.eq_ignore_ascii_case(code) || lint == *name).map(|(code, _)| *code)
+}
+
+fn name(code: &str) -> &'static str {
+  LINTS.iter().find(|(lint, _)| *lint == code).map_or("", |(_, name)| name)
+}
+
+// 'allow W003' or 'allow cancelling_commands' in a comment silences the lint on its line,
+// or on the next line when the comment is on a line of its own
+fn allowed(tokens: &[(Token, Position)]) -> HashMap<usize, HashSet<&'static str>> {
+  let mut allowed: HashMap<usize, HashSet<&'static str>> = HashMap::new();
+  for (token, position) in tokens {
+    if let Token::Comment(text) = token {
+      let lines: Vec<&str> = text.split('\n').collect();
+      for (offset, line) in lines.iter().enumerate() {
+        let own_line = (offset > 0 || position.column == 1) && offset + 1 < lines.len();
+        let line_number = position.line + offset + own_line as usize;
+        let mut words = line.split(|char: char| !char.is_alphanumeric() && char != '_').skip_while(|word| *word != "allow").skip(1);
+        while let Some(code) = words.next().and_then(lint_code) {
+          allowed.entry(line_number).or_default().insert(code);
+        }
+      }
+    }
+  }
+  allowed
+}
+
+fn infinite_loops(instructions: &[Instruction], warnings: &mut Vec<Warning>) {
+  for instruction in instructions {
+    match instruction {
+      Instruction::InfiniteLoop(instructions, position) => {
+        warnings.push(Warning { code: "W004", position: *position, message: String::from("this loop never terminates once entered, its body doesn't change the current cell") });
+        infinite_loops(instructions, warnings);
+      }
+      Instruction::Loop(instructions, _)
+      | Instruction::Procedure(instructions, _) => infinite_loops(instructions, warnings),
+      _ => {}
+    }
+  }
+}
+
+pub fn lint(program: &str, allow: &[&str]) -> Result<Vec<Warning>, Error> {
+  let commands = lex(program);
+  let instructions = optimise(parse(&commands)?);
+  let tokens = lex_with_comments(program);
+  let mut warnings = vec![];
+
+  if let Some((Token::EnterLoop, position)) = commands.first() {
+    warnings.push(Warning { code: "W001", position: *position, message: String::from("this loop never runs, every cell is null when the program starts") });
+  }
+
+  let code: String = commands.iter().filter_map(|(token, _)| token.to_char()).collect();
+  let mut i = 1;
+  while i < commands.len() {
+    let position = commands[i].1;
+    if code[i..].starts_with("[-]") && code[..i].ends_with("[-]") {
+      warnings.push(Warning { code: "W002", position, message: String::from("this clears a cell that the previous '[-]' already cleared") });
+    } else if code[i..].starts_with('[') && code[..i].ends_with(']') {
+      warnings.push(Warning { code: "W005", position, message: String::from("this loop never runs, the current cell is null right after a loop") });
+    } else if let pair @ ("+-" | "-+" | "<>" | "><") = &code[i - 1..=i] {
+      warnings.push(Warning { code: "W003", position: commands[i - 1].1, message: format!("'{}' cancels itself out", pair) });
+      i += 1;
+    }
+    i += 1;
+  }
+
+  infinite_loops(&instructions, &mut warnings);
+
+  // punctuation touching a word, like 'then,' or 'end.', was most likely meant as prose
+  for (i, (token, position)) in tokens.iter().enumerate() {
+    if !token.is_command() {
+      continue;
+    }
+    let touches_word = |neighbour: Option<&(Token, Position)>, last: bool| match neighbour {
+      Some((Token::Comment(text), _)) => {
+        let char = if last { text.chars().last() } else { text.chars().next() };
+        char.is_some_and(char::is_alphanumeric)
+      }
+      _ => false
+    };
+    if touches_word(i.checked_sub(1).and_then(|i| tokens.get(i)), true) || touches_word(tokens.get(i + 1), false) {
+      warnings.push(Warning { code: "W006", position: *position, message: format!("'{}' looks like part of a comment but is a command", token.to_char().unwrap()) });
+    }
+  }
+
+  let allowed = allowed(&tokens);
+  warnings.retain(|warning| {
+    !allow.contains(&warning.code) && !allowed.get(&warning.position.line).is_some_and(|codes| codes.contains(warning.code))
+  });
+  warnings.sort_by_key(|warning| warning.position);
+  Ok(warnings)
+}
+
+pub fn explain(code: &str) -> String {
+  format!("silence {} with --allow {} or with 'allow {}' in a comment at the end of the line or on the line before", code, code, name(code))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn codes(program: &str, allow: &[&str]) -> Vec<(&'static str, String)> {
+    lint(program, allow).unwrap().into_iter().map(|warning| (warning.code, warning.position.to_string())).collect()
+  }
+
+  #[test]
+  fn dead_start_loop() {
+    assert_eq!(codes("[-]+", &[]), [("W001", String::from("1:1"))]);
+    assert_eq!(codes("+[-]", &[]), []);
+  }
+
+  #[test]
+  fn double_clear() {
+    assert_eq!(codes("+[-][-]", &[]), [("W002", String::from("1:5"))]);
+  }
+
+  #[test]
+  fn cancelling_commands() {
+    assert_eq!(codes("+>+-<>", &[]), [("W003", String::from("1:3")), ("W003", String::from("1:5"))]);
+    assert_eq!(codes("+-+", &[]), [("W003", String::from("1:1"))]);
+  }
+
+  #[test]
+  fn infinite_loop() {
+    assert_eq!(codes("+[>+<]", &[]), [("W004", String::from("1:2"))]);
+    assert_eq!(codes("+[>+<-]", &[]), []);
+  }
+
+  #[test]
+  fn dead_loop() {
+    assert_eq!(codes("+[->+<][.]", &[]), [("W005", String::from("1:8"))]);
+  }
+
+  #[test]
+  fn command_in_comment() {
+    assert_eq!(codes("+ then, done", &[]), [("W006", String::from("1:7"))]);
+    assert_eq!(codes("+ then , done", &[]), []);
+  }
+
+  #[test]
+  fn allowed_lints() {
+    assert_eq!(codes("+-", &["W003"]), []);
+    assert_eq!(codes("+- allow cancelling_commands", &[]), []);
+    assert_eq!(codes("allow W003\n+-\n+-", &[]), [("W003", String::from("3:1"))]);
+  }
+
+  #[test]
+  fn lints_by_code_or_name() {
+    assert_eq!(lint_code("w002"), Some("W002"));
+    assert_eq!(lint_code("dead_loop"), Some("W005"));
+    assert_eq!(lint_code("dead-loop"), None);
+  }
+
+  #[test]
+  fn invalid_programs() {
+    assert!(matches!(lint("+[", &[]), Err(Error::UnmatchedOpenLoop(_))));
+  }
+}
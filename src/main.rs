@@ -1,103 +1,707 @@
-use std::io::{stdout, Write};
-use std::num::NonZeroU32;
-use std::time::Instant;
+use std::io::{stdin, stdout, Read, Write};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::time::{Duration, Instant};
 use colored::Colorize;
 use clap::Clap;
+use std::process;
 use std::fs;
 
+mod compiler; use compiler::*;
+mod debugger; use debugger::*;
+mod dialect; use dialect::*;
+mod dump; use dump::*;
 mod error; use error::*;
+mod format; use format::*;
+mod generate; use generate::*;
+mod history; use history::*;
 mod instruction; use instruction::*;
+mod lint; use lint::*;
 mod memory; use memory::*;
+mod minify; use minify::*;
 mod optimise; use optimise::*;
+mod preprocess; use preprocess::*;
+mod profile; use profile::*;
+mod repl; use repl::*;
+mod runtime; use runtime::*;
+mod scheduler; use scheduler::*;
+mod state; use state::*;
 mod token; use token::*;
+mod transpile; use transpile::*;
+mod trace; use trace::*;
+mod watch; use watch::*;
 
 // run
 
-fn run<T>(instructions: &Vec<Instruction>, memory: &mut impl Memory<T>) -> Result<(), Error> {
-  for instruction in instructions {
-    instruction.run(memory)?;
+fn run<T>(instructions: &[Instruction], memory: &mut impl Memory<T>) -> Result<(), Error> {
+  let result = match memory.args().dialect.as_str() {
+    "brainfork" => run_threads(instructions, memory),
+    // calls jump to wherever the procedure is in the program, so they need the debugger's cursor
+    "pbrain" => Debugger::new(instructions, 0).run_to_end(memory),
+    _ => match instructions.iter().try_for_each(|instruction| instruction.run(memory)) {
+      Err(Error::ProgramEnded) => Ok(()),
+      result => result
+    }
+  };
+  result.and_then(|()| memory.flush())
+}
+
+#[allow(unused_must_use)]
+fn execute<T>(instructions: &[Instruction], memory: &mut impl Memory<T>, args: &Args, hash: u64, path: Vec<usize>) -> Result<(), Error> {
+  let mut debugger = match Debugger::with_path(instructions, hash, path) {
+    None => return Err(Error::InvalidState(args.file.clone().unwrap_or_else(|| String::from("<inline>")))),
+    Some(debugger) => debugger
+  };
+  let now = Instant::now();
+  let result = if args.debugger {
+    debugger.run(memory)
+  } else if args.save_state.is_some() || debugger.path() != [0] {
+    debugger.run_to_end(memory)
+  } else {
+    run(instructions, memory)
+  };
+  let result = result.and_then(|()| memory.flush());
+  stdout().flush();
+  let saved = match (&result, &args.save_state) {
+    (Err(Error::StepLimitReached(_) | Error::TimedOut(_, _)), Some(file)) => {
+      State::capture(memory, hash, debugger.path()).and_then(|state| state.save(file)).map(|()| {
+        eprintln!("{} saved the execution state to {}, resume it with 'brainfuck resume {}'", "note:".green(), file.yellow(), file);
+      })
+    }
+    _ => Ok(())
+  };
+  if result.is_ok() && args.timed {
+    let duration = Instant::now() - now;
+    println!("\n{} {}s", "duration:".green(), duration.as_secs_f64());
+  }
+  let dumped = write_dump(memory, args);
+  if let Some(profile) = &memory.runtime().profile {
+    profile.report(memory.runtime().steps);
+  }
+  // what stopped the program is reported before a failure to save or dump what it left behind
+  match (result, saved.and(dumped)) {
+    (Err(err), Err(failure)) => Err(Error::Followed(Box::new(err), Box::new(failure))),
+    (result, failure) => result.and(failure)
+  }
+}
+
+fn read_source(file: &str) -> Result<String, Error> {
+  fs::read_to_string(file).map_err(|_| Error::ReadFileFail(file.to_string()))
+}
+
+// prints the text when there's no output file
+fn write_output(output: Option<&str>, text: &str) -> Result<(), Error> {
+  match output {
+    None => {
+      print!("{}", text);
+      Ok(())
+    }
+    Some(output) => fs::write(output, text).map_err(|_| Error::WriteFileFail(output.to_string()))
+  }
+}
+
+fn read_program(args: &Args) -> Result<(String, Option<SourceMap>), Error> {
+  let content = match (&args.inline, &args.file) {
+    (Some(content), _) => content.clone(),
+    (None, Some(file)) => read_source(file)?,
+    (None, None) => String::new()
+  };
+  // groups like (>+)*3 would swallow the parentheses of procedures
+  if args.preprocess && args.dialect == "pbrain" {
+    return Err(Error::UnsupportedDialect(args.dialect.clone(), "--preprocess"));
+  }
+  if args.preprocess {
+    let (content, map) = preprocess(args.file.as_deref().filter(|_| args.inline.is_none()), &content)?;
+    Ok((content, Some(map)))
+  } else {
+    Ok((content, None))
+  }
+}
+
+fn compile(args: &Args) -> Result<(Vec<Instruction>, u64, Option<SourceMap>), Error> {
+  let (content, map) = read_program(args)?;
+  let tokens = lex_dialect(&content, &Dialect::from_arg(&args.dialect)?);
+  let mut instructions = match (parse(&tokens), &map) {
+    (Err(err), Some(map)) => return Err(map.relocate(err)),
+    (result, _) => result?
+  };
+  if !args.unoptimised {
+    instructions = if args.dialect == "brainfork" { optimise_threaded(instructions) } else { optimise(instructions) };
   }
+  Ok((instructions, hash(&content), map))
+}
+
+fn start(args: &Args, state: Option<&State>) -> Result<(), Error> {
+  let (instructions, hash, map) = compile(args)?;
+  // threads, procedures, call stacks and the register can't be stepped back through
+  let extension = matches!(args.dialect.as_str(), "brainfork" | "pbrain" | "extended");
+  if extension && args.debugger {
+    return Err(Error::UnsupportedDialect(args.dialect.clone(), "the debugger"));
+  } else if !saves(&args.dialect) && args.save_state.is_some() {
+    return Err(Error::UnsupportedDialect(args.dialect.clone(), "--save-state"));
+  } else if !saves(&args.dialect) && state.is_some() {
+    return Err(Error::UnsupportedDialect(args.dialect.clone(), "saved execution states"));
+  }
+  if let Some(state) = state {
+    if state.hash != hash {
+      return Err(Error::ProgramChanged(state.file.clone()));
+    }
+  }
+  let path = state.map_or_else(|| vec![0], |state| state.path.clone());
+  let result = if args.dialect == "boolfuck" {
+    execute(&instructions, &mut Memory1::new(args)?, args, hash, path)
+  } else if args.cell_size == "8" {
+    let mut memory = Memory8::new(args)?;
+    if let Some(state) = state {
+      state.restore(&mut memory)?;
+    }
+    execute(&instructions, &mut memory, args, hash, path)
+  } else if args.cell_size == "16" {
+    let mut memory = Memory16::new(args)?;
+    if let Some(state) = state {
+      state.restore(&mut memory)?;
+    }
+    execute(&instructions, &mut memory, args, hash, path)
+  } else {
+    let mut memory = Memory32::new(args)?;
+    if let Some(state) = state {
+      state.restore(&mut memory)?;
+    }
+    execute(&instructions, &mut memory, args, hash, path)
+  };
+  match map {
+    Some(map) => result.map_err(|err| map.relocate(err)),
+    None => result
+  }
+}
+
+fn resume(file: &str, args: &Args) -> Result<(), Error> {
+  let state = State::load(file)?;
+  let mut args = state.args(args)?;
+  args.read_input()?;
+  start(&args, Some(&state))
+}
+
+fn check(args: &Args) -> Result<(), Error> {
+  let (content, map) = read_program(args)?;
+  let tokens = lex_dialect(&content, &Dialect::from_arg(&args.dialect)?);
+  match (parse(&tokens), &map) {
+    (Err(err), Some(map)) => return Err(map.relocate(err)),
+    (result, _) => result?
+  };
+  let name = args.file.clone().filter(|_| args.inline.is_none()).unwrap_or_else(|| String::from("<inline>"));
+  println!("{} {} is valid ({} commands)", "check:".green(), name.yellow(), tokens.len().to_string().green());
+  Ok(())
+}
+
+fn compile_file(file: &str, output: Option<&str>) -> Result<(), Error> {
+  let content = read_source(file)?;
+  let bf = compile_to_bf(&content)?;
+  write_output(output, &bf)
+}
+
+fn convert_file(file: &str, from: &str, to: &str, output: Option<&str>) -> Result<(), Error> {
+  let content = read_source(file)?;
+  let (from, to) = (Dialect::from_arg(from)?, Dialect::from_arg(to)?);
+  if let Some(command) = from.missing_from(&to) {
+    return Err(Error::MissingCommand(to.name, command));
+  }
+  let converted = convert(&content, &from, &to);
+  write_output(output, &converted)
+}
+
+fn fmt(files: &[String], check: bool, dialect: &str) -> Result<(), Error> {
+  // the layout is built from Brainfuck's single character commands
+  if !Dialect::from_arg(dialect)?.is_brainfuck() {
+    return Err(Error::UnsupportedDialect(dialect.to_string(), "fmt"));
+  }
+  let mut unformatted = 0;
+  for file in files {
+    let content = read_source(file)?;
+    let formatted = format_source(&content)?;
+    if formatted == content {
+      continue;
+    }
+    if check {
+      println!("{} {} isn't formatted", "fmt:".green(), file.yellow());
+      unformatted += 1;
+    } else {
+      write_output(Some(file), &formatted)?;
+      println!("{} formatted {}", "fmt:".green(), file.yellow());
+    }
+  }
+  match unformatted {
+    0 => Ok(()),
+    files => Err(Error::Unformatted(files))
+  }
+}
+
+fn lint_files(files: &[String], allow: &[String], deny: bool, dialect: &str) -> Result<(), Error> {
+  // the rules look for patterns of Brainfuck characters, in the code and in comments
+  if !Dialect::from_arg(dialect)?.is_brainfuck() {
+    return Err(Error::UnsupportedDialect(dialect.to_string(), "lint"));
+  }
+  let allow = allow.iter().map(|lint| lint_code(lint).ok_or_else(|| Error::UnknownLint(lint.clone()))).collect::<Result<Vec<_>, _>>()?;
+  let mut codes = vec![];
+  for file in files {
+    let content = read_source(file)?;
+    for warning in lint(&content, &allow)? {
+      println!("{}:{}: {}", file.yellow(), warning.position, warning);
+      if !codes.contains(&warning.code) {
+        codes.push(warning.code);
+      }
+    }
+  }
+  for code in &codes {
+    println!("{} {}", "note:".green(), explain(code));
+  }
+  if deny && !codes.is_empty() {
+    Err(Error::LintWarnings)
+  } else {
+    Ok(())
+  }
+}
+
+fn sample_run<T>(instructions: &[Instruction], memory: &mut impl Memory<T>) -> (Result<(), Error>, String, Vec<u32>) {
+  let runtime = memory.runtime_mut();
+  runtime.captured = Some(String::new());
+  runtime.input.get_or_insert_with(VecDeque::new);
+  let result = run(instructions, memory);
+  (result, memory.runtime_mut().captured.take().unwrap_or_default(), memory.cells())
+}
+
+// the unoptimised original never detects an infinite loop, so unless --max-steps or --timeout
+// is given the sample runs stop after this many steps
+const SAMPLE_STEPS: u64 = 10_000_000;
+
+// runs both programs on the sample input and compares their output, final tape and how they ended,
+// the original unoptimised so the optimiser can't hide a difference, returns why they couldn't be
+// compared when a limit stopped either of them or one wanted input that wasn't given
+fn verify(original: &str, minified: &str, args: &Args) -> Result<Option<&'static str>, Error> {
+  let args = &Args { max_steps: args.max_steps.or(Some(SAMPLE_STEPS).filter(|_| args.timeout.is_none())), ..args.clone() };
+  let compile = |program: &str, unoptimised: bool| {
+    compile(&Args { inline: Some(program.to_string()), preprocess: false, unoptimised, ..args.clone() }).map(|(instructions, _, _)| instructions)
+  };
+  let (original, minified) = (compile(original, true)?, compile(minified, args.unoptimised)?);
+  let (before, after) = if args.cell_size == "8" {
+    (sample_run(&original, &mut Memory8::new(args)?), sample_run(&minified, &mut Memory8::new(args)?))
+  } else if args.cell_size == "16" {
+    (sample_run(&original, &mut Memory16::new(args)?), sample_run(&minified, &mut Memory16::new(args)?))
+  } else {
+    (sample_run(&original, &mut Memory32::new(args)?), sample_run(&minified, &mut Memory32::new(args)?))
+  };
+  let ended = |result: &Result<(), Error>| result.as_ref().err().map(std::mem::discriminant);
+  match (&before.0, &after.0) {
+    (Err(Error::StepLimitReached(_)), _) | (_, Err(Error::StepLimitReached(_))) => Ok(Some("not verified (step limit reached)")),
+    (Err(Error::TimedOut(_, _)), _) | (_, Err(Error::TimedOut(_, _))) => Ok(Some("not verified (timed out)")),
+    (Err(Error::NoInput), _) | (_, Err(Error::NoInput)) if args.program_input.is_none() => Ok(Some("not verified (no input was given)")),
+    (result1, result2) => if ended(result1) == ended(result2) && before.1 == after.1 && before.2 == after.2 {
+      Ok(None)
+    } else {
+      Err(Error::MinifyMismatch)
+    }
+  }
+}
+
+fn verify_text<T, M: Memory<T>>(instructions: &[Instruction], memory: &mut M, text: &str) -> Result<bool, Error> {
+  for char in text.chars() {
+    M::char_to_value(char)?;
+  }
+  let (result, output, _) = sample_run(instructions, memory);
+  result?;
+  Ok(output == text)
+}
+
+fn gen_text(text: &str, strategy: &str, args: &Args, output: Option<&str>) -> Result<(), Error> {
+  let program = generate_text(text, strategy);
+  // the generated program is Brainfuck whatever dialect the options name
+  let args = &Args { dialect: String::from("brainfuck"), ..args.clone() };
+  let (instructions, _, _) = compile(&Args { inline: Some(program.clone()), preprocess: false, ..args.clone() })?;
+  let verified = if args.cell_size == "8" {
+    verify_text(&instructions, &mut Memory8::new(args)?, text)?
+  } else if args.cell_size == "16" {
+    verify_text(&instructions, &mut Memory16::new(args)?, text)?
+  } else {
+    verify_text(&instructions, &mut Memory32::new(args)?, text)?
+  };
+  if !verified {
+    return Err(Error::GeneratedTextMismatch);
+  }
+  eprintln!("{} {} commands, verified by running them", "gen-text:".green(), program.len().to_string().green());
+  write_output(output, &format!("{}\n", program))
+}
+
+fn minify(args: &Args, output: Option<&str>) -> Result<(), Error> {
+  let (program, _) = read_program(args)?;
+  let dialect = Dialect::from_arg(&args.dialect)?;
+  if dialect.is_extended() || dialect.has_bit_cells() {
+    return Err(Error::UnsupportedDialect(dialect.name, "minify"));
+  }
+  let program = if dialect.is_brainfuck() { program } else { convert(&program, &dialect, &Dialect::brainfuck()) };
+  let args = &Args { dialect: String::from("brainfuck"), ..args.clone() };
+  let minified = minify_source(&program)?;
+  match verify(&program, &minified, args)? {
+    None => eprintln!("{} {} bytes down to {}, verified on the sample input", "minify:".green(), program.len().to_string().green(), minified.len().to_string().green()),
+    Some(reason) => eprintln!("{} {} bytes down to {}, {}", "minify:".yellow(), program.len().to_string().green(), minified.len().to_string().green(), reason)
+  }
+  write_output(output, &format!("{}\n", minified))
+}
+
+fn transpile(args: &Args, output: Option<&str>) -> Result<(), Error> {
+  let dialect = Dialect::from_arg(&args.dialect)?;
+  if dialect.is_extended() || dialect.has_bit_cells() {
+    return Err(Error::UnsupportedDialect(dialect.name, "transpile"));
+  }
+  // the C program neither checks the pointer nor cell overflows
+  if args.wrap_around {
+    return Err(Error::UnsupportedOption("--wrap-around", "transpile"));
+  } else if args.no_overflows {
+    return Err(Error::UnsupportedOption("--no-overflows", "transpile"));
+  }
+  let (instructions, _, _) = compile(args)?;
+  let c = transpile_c(&instructions, args);
+  write_output(output, &c)
+}
+
+fn bench_run<T>(instructions: &[Instruction], memory: &mut impl Memory<T>) -> Result<Duration, Error> {
+  memory.runtime_mut().silent = true;
+  let now = Instant::now();
+  run(instructions, memory)?;
+  Ok(Instant::now() - now)
+}
+
+fn bench(args: &Args, runs: NonZeroU32) -> Result<(), Error> {
+  let (instructions, _, _) = compile(args)?;
+  let mut durations = vec![];
+  for _ in 0..runs.get() {
+    durations.push(if args.dialect == "boolfuck" {
+      bench_run(&instructions, &mut Memory1::new(args)?)?
+    } else if args.cell_size == "8" {
+      bench_run(&instructions, &mut Memory8::new(args)?)?
+    } else if args.cell_size == "16" {
+      bench_run(&instructions, &mut Memory16::new(args)?)?
+    } else {
+      bench_run(&instructions, &mut Memory32::new(args)?)?
+    });
+  }
+  let total: Duration = durations.iter().sum();
+  println!("{} {} runs", "bench:".green(), runs.to_string().green());
+  println!("  min  {}s", durations.iter().min().unwrap().as_secs_f64().to_string().green());
+  println!("  mean {}s", (total / runs.get()).as_secs_f64().to_string().green());
+  println!("  max  {}s", durations.iter().max().unwrap().as_secs_f64().to_string().green());
   Ok(())
 }
 
 // main
 
+const SUBCOMMANDS: &[&str] = &["run", "check", "compile", "convert", "fmt", "gen-text", "lint", "minify", "transpile", "debug", "bench", "repl", "resume", "help", "-h", "--help", "-V", "--version"];
+
 #[derive(Clap, Debug)]
 #[clap(version = env!("CARGO_PKG_VERSION"))]
+pub struct Cli {
+  #[clap(subcommand)]
+  command: Command
+}
+
+#[derive(Clap, Debug)]
+pub enum Command {
+  #[clap(about = "Runs a Brainfuck program, 'brainfuck <file>' is a shorthand for 'brainfuck run <file>'")]
+  Run(Program),
+
+  #[clap(about = "Checks that a Brainfuck program is valid without running it")]
+  Check(Program),
+
+  #[clap(about = "Compiles a program written with variables, while, if, print and read to Brainfuck")]
+  Compile {
+    #[clap(about = "The file to compile")]
+    file: String,
+
+    #[clap(short, long, about = "Writes the Brainfuck program to this file instead of stdout")]
+    output: Option<String>
+  },
+
+  #[clap(about = "Rewrites a program from one dialect to another, like Ook! to Brainfuck")]
+  Convert {
+    #[clap(about = "The file to convert")]
+    file: String,
+
+    #[clap(short, long, about = "The dialect the file is written in, one of brainfuck, ook, blub, alphuck, brainfork, pbrain, extended, boolfuck or a dialect file", default_value = "brainfuck")]
+    from: String,
+
+    #[clap(short, long, about = "The dialect to convert to, one of brainfuck, ook, blub, alphuck, brainfork, pbrain, extended, boolfuck or a dialect file", default_value = "brainfuck")]
+    to: String,
+
+    #[clap(short, long, about = "Writes the converted program to this file instead of stdout")]
+    output: Option<String>
+  },
+
+  #[clap(about = "Re-indents Brainfuck files by loop nesting, keeping their comments")]
+  Fmt {
+    #[clap(about = "The Brainfuck files to format in place", required = true)]
+    files: Vec<String>,
+
+    #[clap(long, about = "Only checks that the files are formatted, without changing them")]
+    check: bool,
+
+    #[clap(long, about = "The dialect the files are written in, only brainfuck can be formatted", default_value = "brainfuck")]
+    dialect: String
+  },
+
+  #[clap(about = "Generates a Brainfuck program that prints a text, checking it with the interpreter")]
+  GenText {
+    #[clap(about = "The text the program prints")]
+    text: String,
+
+    #[clap(short, long, about = "How to build the characters, best tries every strategy and keeps the shortest program", default_value = "best", possible_values = STRATEGIES)]
+    strategy: String,
+
+    #[clap(short, long, about = "Writes the program to this file instead of stdout")]
+    output: Option<String>,
+
+    #[clap(flatten)]
+    args: Args
+  },
+
+  #[clap(about = "Warns about suspicious code in Brainfuck files")]
+  Lint {
+    #[clap(about = "The Brainfuck files to lint", required = true)]
+    files: Vec<String>,
+
+    #[clap(short, long, multiple_occurrences = true, number_of_values = 1, about = "Silences a lint, by code like W003 or by name like cancelling_commands")]
+    allow: Vec<String>,
+
+    #[clap(short, long, about = "Exits with an error when there are warnings")]
+    deny: bool,
+
+    #[clap(long, about = "The dialect the files are written in, only brainfuck can be linted", default_value = "brainfuck")]
+    dialect: String
+  },
+
+  #[clap(about = "Strips comments and redundant commands from a Brainfuck program, checking the result on a sample input")]
+  Minify {
+    #[clap(flatten)]
+    program: Program,
+
+    #[clap(short, long, about = "Writes the minified program to this file instead of stdout")]
+    output: Option<String>
+  },
+
+  #[clap(about = "Translates a Brainfuck program to C")]
+  Transpile {
+    #[clap(flatten)]
+    program: Program,
+
+    #[clap(short, long, about = "Writes the C program to this file instead of stdout")]
+    output: Option<String>
+  },
+
+  #[clap(about = "Runs a Brainfuck program in the interactive debugger")]
+  Debug(Program),
+
+  #[clap(about = "Runs a Brainfuck program several times without printing its output and reports how long it took")]
+  Bench {
+    #[clap(flatten)]
+    program: Program,
+
+    #[clap(short, long, about = "How many times to run the program", default_value = "10")]
+    runs: NonZeroU32
+  },
+
+  #[clap(about = "Runs Brainfuck line by line on a persistent tape")]
+  Repl(Args),
+
+  #[clap(about = "Resumes a program from a saved execution state, with the settings it was started with")]
+  Resume {
+    #[clap(about = "The state file to resume from")]
+    state: String,
+
+    #[clap(flatten)]
+    args: Args
+  }
+}
+
+#[derive(Clap, Debug)]
+pub struct Program {
+  #[clap(about = "The Brainfuck file to run, - reads the program from stdin up to the first '!' and the program's input after it", required_unless_present = "execute")]
+  file: Option<String>,
+
+  #[clap(short, long, about = "Runs this code instead of a file", conflicts_with = "file")]
+  execute: Option<String>,
+
+  #[clap(flatten)]
+  args: Args
+}
+
+impl Program {
+  fn args(self) -> Result<Args, Error> {
+    let mut args = self.args;
+    match (self.file, self.execute) {
+      (_, Some(code)) => args.inline = Some(code),
+      (Some(file), None) if file == "-" => {
+        let mut content = String::new();
+        if stdin().read_to_string(&mut content).is_err() {
+          return Err(Error::ReadProgramFail);
+        }
+        // ! is a command in Extended Brainfuck, so the whole of stdin is the program
+        match content.split_once('!').filter(|_| args.dialect != "extended") {
+          None => args.inline = Some(content),
+          Some((program, input)) => {
+            args.inline = Some(program.to_string());
+            args.program_input = Some(input.to_string());
+          }
+        }
+      }
+      (file, None) => args.file = file
+    }
+    args.read_input()?;
+    Ok(args)
+  }
+}
+
+impl Args {
+  // --input and --input-string can't be combined with input given after a program read from stdin
+  fn read_input(&mut self) -> Result<(), Error> {
+    let input = match (&self.input, &self.input_string) {
+      (Some(path), _) => match fs::read_to_string(path) {
+        Err(_) => return Err(Error::ReadInputFileFail(path.clone())),
+        Ok(input) => input
+      }
+      (None, Some(input)) => input.clone(),
+      (None, None) => return Ok(())
+    };
+    if self.program_input.is_some() {
+      Err(Error::ConflictingInput)
+    } else {
+      self.program_input = Some(input);
+      Ok(())
+    }
+  }
+}
+
+#[derive(Clap, Debug, Clone)]
 pub struct Args {
-  #[clap(about = "The Brainfuck file to run")]
-  file: String,
+  #[clap(skip)]
+  file: Option<String>,
+
+  #[clap(skip)]
+  inline: Option<String>,
+
+  #[clap(skip)]
+  program_input: Option<String>,
 
-  #[clap(short, long, about = "Set the size of cells in bits", default_value = "8", possible_values = &["8", "16", "32"])]
+  #[clap(skip)]
+  debugger: bool,
+  #[clap(short, long, about = "Set the size of cells in bits, Boolfuck always uses single bits", default_value = "8", possible_values = &["8", "16", "32"])]
   cell_size: String,
 
+  #[clap(long, about = "The dialect the program is written in, one of brainfuck, ook, blub, alphuck, brainfork, pbrain, extended, boolfuck or a dialect file", default_value = "brainfuck")]
+  dialect: String,
+
+  #[clap(long, about = "How Brainfork threads take turns, round-robin runs one instruction of each in the order they were forked, os runs each on its own thread", default_value = "round-robin", possible_values = &["round-robin", "os"])]
+  scheduler: String,
+
   #[clap(short, long, about = "Set the number of cells in memory", default_value = "30000")]
   memory_size: NonZeroU32,
 
+  #[clap(long, about = "Fills memory from this file before running the program")]
+  init_memory: Option<String>,
+
+  #[clap(long, about = "The format of the initial memory file", default_value = "csv", possible_values = &["csv", "raw"])]
+  init_format: String,
+
+  #[clap(long, about = "Set the initial position of the pointer", default_value = "0")]
+  init_pointer: u32,
+
+  #[clap(short, long, about = "Reads the program's input from this file instead of stdin", conflicts_with = "input-string")]
+  input: Option<String>,
+
+  #[clap(long, about = "Uses this text as the program's input instead of stdin")]
+  input_string: Option<String>,
+
   #[clap(short, long, about = "Wrap around when reaching the leftmost or rightmost cell")]
   wrap_around: bool,
 
   #[clap(short, long, about = "Exit on cell overflows")]
   no_overflows: bool,
 
+  #[clap(long, about = "Exit after executing this many steps, counted from where a resumed program left off")]
+  max_steps: Option<u64>,
+
+  #[clap(long, about = "Exit after running for this many seconds")]
+  timeout: Option<f64>,
+
+  #[clap(long, about = "Exit after writing this many bytes of output")]
+  max_output: Option<u64>,
+
+  #[clap(long, about = "Refuse to allocate more than this many bytes of memory")]
+  max_memory: Option<u64>,
+
+  #[clap(long, about = "Expands macros, repetitions like +*10 and include \"file.bf\" lines before running the program")]
+  preprocess: bool,
+
   #[clap(short, long, about = "Disables all optimisations")]
   unoptimised: bool,
 
+  #[clap(long, about = "Prints the memory once the program ends", possible_values = &["hex", "decimal", "json", "raw"])]
+  dump_memory: Option<String>,
+
+  #[clap(long, about = "Writes the memory dump to this file instead of stderr")]
+  dump_file: Option<String>,
+
   #[clap(short, long, about = "Prints how long the program took to execute")]
   timed: bool,
 
+  #[clap(short, long, about = "Prints the instructions and loops that took the most steps to stderr")]
+  profile: bool,
+
+  #[clap(long, about = "Logs every executed instruction to a file")]
+  trace: Option<String>,
+
+  #[clap(long, about = "Only log one instruction every N steps", default_value = "1")]
+  trace_every: NonZeroU64,
+
+  #[clap(long, about = "Start logging at this step")]
+  trace_from: Option<u64>,
+
+  #[clap(long, about = "Stop logging after this step")]
+  trace_to: Option<u64>,
+
+  #[clap(long, multiple_occurrences = true, number_of_values = 1, about = "Reports whenever a cell changes, or becomes a value with <cell>=<value>, ranges are written <from>-<to>")]
+  watch: Vec<Watchpoint>,
+
+  #[clap(long, about = "Saves the execution state to this file when a limit interrupts the program")]
+  save_state: Option<String>,
+
   #[clap(short, long, about = "Printing the current cell prints debug information")]
   debug: bool
 }
 
-#[allow(unused_must_use)]
 fn main() {
-  let args = Args::parse();
-  if let Ok(content) = fs::read_to_string(&args.file) {
-    let tokens = lex(&content);
-    match parse(&tokens) {
-      Ok(mut instructions) => {
-        if !args.unoptimised {
-          instructions = optimise(instructions);
-        }
-        let now = Instant::now();
-        if args.cell_size == "8" {
-          let mut memory = Memory8::new(&args);
-          if let Err(err) = run(&instructions, &mut memory) {
-            stdout().flush();
-            eprintln!("\n{} {}", "runtime error:".red(), err.message());
-          } else if args.timed {
-            stdout().flush();
-            let duration = Instant::now() - now;
-            println!("\n{} {}s", "duration:".green(), duration.as_secs_f64());
-          }
-        } else if args.cell_size == "16" {
-          let mut memory = Memory16::new(&args);
-          if let Err(err) = run(&instructions, &mut memory) {
-            stdout().flush();
-            eprintln!("\n{} {}", "runtime error:".red(), err.message());
-          } else if args.timed {
-            stdout().flush();
-            let duration = Instant::now() - now;
-            println!("\n{} {}s", "duration:".green(), duration.as_secs_f64());
-          }
-        } else if args.cell_size == "32" {
-          let mut memory = Memory32::new(&args);
-          if let Err(err) = run(&instructions, &mut memory) {
-            stdout().flush();
-            eprintln!("\n{} {}", "runtime error:".red(), err.message());
-          } else if args.timed {
-            stdout().flush();
-            let duration = Instant::now() - now;
-            println!("\n{} {}s", "duration:".green(), duration.as_secs_f64());
-          }
-        }
-      }
-      Err(err) => {
-        eprintln!("{} {}", "compilation error:".red(), err.message());
-      }
+  let mut arguments: Vec<OsString> = std::env::args_os().collect();
+  if let Some(argument) = arguments.get(1) {
+    if !SUBCOMMANDS.iter().any(|subcommand| argument == subcommand) {
+      arguments.insert(1, OsString::from("run"));
     }
-  } else {
-    eprintln!("{} {}", "error:".red(), "Couldn't read the file, are you sure the path is valid?");
   }
-}
\ No newline at end of file
+  let result = match Cli::parse_from(arguments).command {
+    Command::Run(program) => program.args().and_then(|args| start(&args, None)),
+    Command::Check(program) => program.args().and_then(|args| check(&args)),
+    Command::Compile { file, output } => compile_file(&file, output.as_deref()),
+    Command::Convert { file, from, to, output } => convert_file(&file, &from, &to, output.as_deref()),
+    Command::Fmt { files, check, dialect } => fmt(&files, check, &dialect),
+    Command::GenText { text, strategy, output, args } => gen_text(&text, &strategy, &args, output.as_deref()),
+    Command::Lint { files, allow, deny, dialect } => lint_files(&files, &allow, deny, &dialect),
+    Command::Minify { program, output } => program.args().and_then(|args| minify(&args, output.as_deref())),
+    Command::Transpile { program, output } => program.args().and_then(|args| transpile(&args, output.as_deref())),
+    Command::Debug(program) => program.args().and_then(|args| start(&Args { debugger: true, ..args }, None)),
+    Command::Bench { program, runs } => program.args().and_then(|args| bench(&args, runs)),
+    Command::Repl(mut args) => args.read_input().and_then(|()| repl(&args)),
+    Command::Resume { state, args } => resume(&state, &args)
+  };
+  if let Err(err) = result {
+    stdout().flush().ok();
+    eprintln!("{} {}", err.label().red(), err.message());
+    process::exit(1);
+  }
+}
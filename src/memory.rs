@@ -1,24 +1,103 @@
 use std::io::{stdin, stdout, Write};
-use crate::{Args, Error};
+use std::fs;
+use crate::{Args, Change, Error, Instruction, Position, Runtime};
 use colored::Colorize;
 use std::fmt::Debug;
+use std::mem::size_of;
 
 // memory
 
+fn check_memory_limit(bytes: usize, args: &Args) -> Result<(), Error> {
+  match args.max_memory {
+    Some(max_memory) if bytes as u64 > max_memory => Err(Error::MemoryLimitExceeded(bytes as u64, max_memory)),
+    _ => Ok(())
+  }
+}
+
+pub fn allocate<T: Clone + Default>(args: &Args) -> Result<Vec<T>, Error> {
+  let cells = u32::from(args.memory_size) as usize;
+  check_memory_limit(cells.saturating_mul(size_of::<T>()), args)?;
+  Ok(vec![T::default(); cells])
+}
+
+// one cell per bit, eight to a byte
+pub fn allocate_bits(args: &Args) -> Result<Vec<u8>, Error> {
+  let bytes = (u32::from(args.memory_size) as usize).div_ceil(8);
+  check_memory_limit(bytes, args)?;
+  Ok(vec![0; bytes])
+}
+
+mod memory1; pub use memory1::*;
 mod memory8; pub use memory8::*;
 mod memory16; pub use memory16::*;
 mod memory32; pub use memory32::*;
 
-pub trait Memory<T>: Debug {
+// Send so that Brainfork threads can share the tape across OS threads
+pub trait Memory<T>: Debug + Send {
   fn args(&self) -> &Args;
   fn size(&self) -> u32;
   fn null() -> T;
 
+  // runtime
+
+  fn runtime(&self) -> &Runtime;
+  fn runtime_mut(&mut self) -> &mut Runtime;
+
+  // Instruction::run has too many arms for the compiler to inline what it calls on its own, so the
+  // methods run for every instruction are forced inline and what only instrumented runs need isn't
+  #[inline(always)]
+  fn step(&mut self, instruction: &Instruction) -> Result<(), Error> {
+    if self.runtime().instrumented {
+      self.instrument_step(instruction)
+    } else {
+      Ok(())
+    }
+  }
+  #[inline(always)]
+  fn iterate(&mut self, position: Position) -> Result<(), Error> {
+    if self.runtime().instrumented {
+      self.instrument_iteration(position)
+    } else {
+      Ok(())
+    }
+  }
+  #[inline(never)]
+  fn instrument_step(&mut self, instruction: &Instruction) -> Result<(), Error> {
+    let runtime = self.runtime_mut();
+    runtime.tick()?;
+    if let Some(profile) = &mut runtime.profile {
+      profile.count(instruction);
+    }
+    self.log(|| instruction.to_string(), instruction.position())
+  }
+  #[inline(never)]
+  fn instrument_iteration(&mut self, position: Position) -> Result<(), Error> {
+    let runtime = self.runtime_mut();
+    runtime.tick()?;
+    if let Some(profile) = &mut runtime.profile {
+      profile.count_iteration(position);
+    }
+    self.log(|| String::from("iterate"), position)
+  }
+  // the pointer and cell are only read for the steps the trace keeps
+  fn log(&mut self, instruction: impl FnOnce() -> String, position: Position) -> Result<(), Error> {
+    let steps = self.runtime().steps;
+    if !self.runtime().trace.as_ref().is_some_and(|trace| trace.wants(steps)) {
+      return Ok(());
+    }
+    let (pointer, value) = (self.pointer(), Self::value_to_string(self.current()));
+    match &mut self.runtime_mut().trace {
+      None => Ok(()),
+      Some(trace) => trace.log(steps, &instruction(), pointer, &value, position)
+    }
+  }
+
   // pointer
 
   fn pointer(&self) -> u32;
   fn pointer_mut(&mut self) -> &mut u32;
 
+  #[inline(always)]
   fn add_pointer(&self, n: u32) -> Result<u32, Error> {
     if self.size() > self.pointer() + n {
       Ok(self.pointer() + n)
@@ -28,6 +107,7 @@ pub trait Memory<T>: Debug {
       Err(Error::RightMostCell)
     }
   }
+  #[inline(always)]
   fn sub_pointer(&self, n: u32) -> Result<u32, Error> {
     if self.pointer() >= n {
       Ok(self.pointer() - n)
@@ -38,60 +118,151 @@ pub trait Memory<T>: Debug {
     }
   }
 
+  #[inline(always)]
+  fn set_pointer(&mut self, pointer: u32) {
+    if self.runtime().instrumented {
+      self.record_pointer();
+    }
+    *self.pointer_mut() = pointer;
+  }
+  #[inline(never)]
+  fn record_pointer(&mut self) {
+    let previous = self.pointer();
+    if let Some(history) = &mut self.runtime_mut().history {
+      history.record(Change::Pointer(previous));
+    }
+  }
+  #[inline(always)]
   fn incr_pointer(&mut self, n: u32) -> Result<(), Error> {
-    *self.pointer_mut() = self.add_pointer(n)?;
+    let pointer = self.add_pointer(n)?;
+    self.set_pointer(pointer);
     Ok(())
   }
+  #[inline(always)]
   fn decr_pointer(&mut self, n: u32) -> Result<(), Error> {
-    *self.pointer_mut() = self.sub_pointer(n)?;
+    let pointer = self.sub_pointer(n)?;
+    self.set_pointer(pointer);
     Ok(())
   }
 
   // memory
 
   fn value(&self, pointer: u32) -> T;
-  fn value_mut(&mut self, pointer: u32) -> &mut T;
+  // changes a cell without recording it in the history or checking watchpoints
+  fn put_value(&mut self, pointer: u32, value: T);
   fn value_is_null(&self, value: T) -> bool;
 
   fn add_value(&self, pointer: u32, n: u32) -> Result<T, Error>;
   fn sub_value(&self, pointer: u32, n: u32) -> Result<T, Error>;
 
+  #[inline(always)]
+  fn set_value(&mut self, pointer: u32, value: T) {
+    if self.runtime().instrumented {
+      self.record_value(pointer, value);
+    } else {
+      self.put_value(pointer, value);
+    }
+  }
+  #[inline(never)]
+  fn record_value(&mut self, pointer: u32, value: T) {
+    let previous = Self::value_to_u32(self.value(pointer));
+    if let Some(history) = &mut self.runtime_mut().history {
+      history.record(Change::Cell(pointer, previous));
+    }
+    self.put_value(pointer, value);
+    if !self.runtime().watchpoints.is_empty() {
+      let value = Self::value_to_u32(self.value(pointer));
+      self.runtime_mut().watch(pointer, previous, value);
+    }
+  }
+  #[inline(always)]
   fn incr_value(&mut self, pointer: u32, n: u32) -> Result<(), Error> {
-    *self.value_mut(pointer) = self.add_value(pointer, n)?;
+    let value = self.add_value(pointer, n)?;
+    self.set_value(pointer, value);
     Ok(())
   }
+  #[inline(always)]
   fn decr_value(&mut self, pointer: u32, n: u32) -> Result<(), Error> {
-    *self.value_mut(pointer) = self.sub_value(pointer, n)?;
+    let value = self.sub_value(pointer, n)?;
+    self.set_value(pointer, value);
     Ok(())
   }
+  #[inline(always)]
   fn clear_value(&mut self, pointer: u32) -> Result<(), Error> {
-    *self.value_mut(pointer) = Self::null();
+    self.set_value(pointer, Self::null());
     Ok(())
   }
 
+  // fills the tape from --init-memory, either comma separated values
+  // or raw little endian cells of the selected size
+  fn initialise(&mut self) -> Result<(), Error> {
+    if let Some(path) = &self.args().init_memory {
+      let width = size_of::<T>();
+      let values: Vec<u32> = if self.args().init_format == "raw" {
+        match fs::read(path) {
+          Err(_) => return Err(Error::ReadInitMemoryFail(path.clone())),
+          Ok(bytes) => bytes.chunks(width).map(|chunk| {
+            chunk.iter().rev().fold(0, |value, &byte| value << 8 | byte as u32)
+          }).collect()
+        }
+      } else {
+        match fs::read_to_string(path) {
+          Err(_) => return Err(Error::ReadInitMemoryFail(path.clone())),
+          Ok(content) => content.split(|char: char| char == ',' || char.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse().map_err(|_| Error::InvalidInitValue(value.to_string())))
+            .collect::<Result<_, _>>()?
+        }
+      };
+      if values.len() > self.size() as usize {
+        return Err(Error::InitMemoryTooLarge(values.len(), self.size()));
+      }
+      for (pointer, &value) in values.iter().enumerate() {
+        if Self::value_to_u32(Self::value_from_u32(value)) != value {
+          return Err(Error::InitValueTooLarge(pointer as u32, value, Self::bits()));
+        }
+        self.put_value(pointer as u32, Self::value_from_u32(value));
+      }
+    }
+    let pointer = self.args().init_pointer;
+    if pointer >= self.size() {
+      return Err(Error::InitPointerOutOfBounds(pointer, self.size()));
+    }
+    *self.pointer_mut() = pointer;
+    Ok(())
+  }
+
+  fn cells(&self) -> Vec<u32> {
+    (0..self.size()).map(|pointer| Self::value_to_u32(self.value(pointer))).collect()
+  }
+
+  #[inline(always)]
   fn current(&self) -> T {
     self.value(self.pointer())
   }
-  fn current_mut(&mut self) -> &mut T {
-    self.value_mut(self.pointer())
-  }
+  #[inline(always)]
   fn current_is_null(&self) -> bool {
     self.value_is_null(self.current())
   }
 
+  #[allow(dead_code)]
   fn add_current(&self, n: u32) -> Result<T, Error> {
     self.add_value(self.pointer(), n)
   }
+  #[allow(dead_code)]
   fn sub_current(&self, n: u32) -> Result<T, Error> {
     self.sub_value(self.pointer(), n)
   }
 
+  #[inline(always)]
   fn incr_current(&mut self, n: u32) -> Result<(), Error> {
     self.incr_value(self.pointer(), n)
   }
+  #[inline(always)]
   fn decr_current(&mut self, n: u32) -> Result<(), Error> {
     self.decr_value(self.pointer(), n)
   }
+  #[inline(always)]
   fn clear_current(&mut self) -> Result<(), Error> {
     self.clear_value(self.pointer())
   }
@@ -99,57 +270,78 @@ pub trait Memory<T>: Debug {
   // read / write
 
   fn value_to_u32(value: T) -> u32;
+  fn value_from_u32(value: u32) -> T;
+  fn bits() -> u32 {
+    Self::value_to_u32(Self::value_from_u32(u32::MAX)).count_ones()
+  }
   fn value_to_string(value: T) -> String;
   fn value_to_char(value: T) -> Result<char, Error>;
   fn char_to_value(char: char) -> Result<T, Error>;
 
-  fn write(&self) -> Result<(), Error> {
+  fn write(&mut self) -> Result<(), Error> {
     if self.args().debug {
-      match Self::value_to_char(self.current()) {
-        Ok(char) => {
-          println!("{} [pointer: {}, value: {}, character: '{}']",
-            "debug:".green(),
-            self.pointer().to_string().green(),
-            Self::value_to_string(self.current()).green(),
-            char.to_string().yellow()
-          );
-        }
-        Err(_) => {
-          println!("{} [pointer: {}, value: {}, invalid character]",
-            "debug:".green(),
-            self.pointer().to_string().green(),
-            Self::value_to_string(self.current()).green(),
-          );
-        }
-      }
-      Ok(())
+      self.write_debug()
     } else {
-      match Self::value_to_char(self.current()) {
-        Err(err) => Err(err),
-        Ok(char) => {
-          print!("{}", char);
-          Ok(())
-        }
+      let char = Self::value_to_char(self.current())?;
+      self.emit(&char.to_string())
+    }
+  }
+
+  fn write_debug(&mut self) -> Result<(), Error> {
+    let line = match Self::value_to_char(self.current()) {
+      Ok(char) => {
+        format!("{} [pointer: {}, value: {}, character: '{}']",
+          "debug:".green(),
+          self.pointer().to_string().green(),
+          Self::value_to_string(self.current()).green(),
+          char.to_string().yellow()
+        )
+      }
+      Err(_) => {
+        format!("{} [pointer: {}, value: {}, invalid character]",
+          "debug:".green(),
+          self.pointer().to_string().green(),
+          Self::value_to_string(self.current()).green(),
+        )
       }
+    };
+    self.emit(&format!("{}\n", line))
+  }
+
+  fn emit(&mut self, text: &str) -> Result<(), Error> {
+    self.runtime_mut().count_output(text.len())?;
+    if let Some(captured) = &mut self.runtime_mut().captured {
+      *captured += text;
+    } else if !self.runtime().silent {
+      print!("{}", text);
     }
+    Ok(())
   }
 
-  fn read(&mut self) -> Result<(), Error> {
+  // output that's waiting for more bits before it can be written
+  fn flush(&mut self) -> Result<(), Error> {
+    Ok(())
+  }
+
+  fn next_char(&mut self) -> Result<char, Error> {
+    if let Some(input) = &mut self.runtime_mut().input {
+      return input.pop_front().ok_or(Error::NoInput);
+    }
     if stdout().flush().is_err() {
       return Err(Error::WriteOutputFail)
     }
     let mut input = String::new();
     match stdin().read_line(&mut input) {
       Err(_) => Err(Error::ReadInputFail),
-      Ok(size) => {
-        if size == 0 {
-          Err(Error::NoInput)
-        } else {
-          let char = input.chars().nth(0).unwrap();
-          *self.current_mut() = Self::char_to_value(char)?;
-          Ok(())
-        }
-      }
+      Ok(0) => Err(Error::NoInput),
+      Ok(_) => Ok(input.chars().next().unwrap())
     }
   }
+
+  fn read(&mut self) -> Result<(), Error> {
+    let char = self.next_char()?;
+    let value = Self::char_to_value(char)?;
+    self.set_value(self.pointer(), value);
+    Ok(())
+  }
 }
\ No newline at end of file
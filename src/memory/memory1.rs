@@ -0,0 +1,150 @@
+use crate::{Args, Error, Memory, Runtime, allocate_bits};
+
+// the tape of Boolfuck, input and output go through bytes one bit at a time, least significant first
+#[derive(Debug)]
+pub struct Memory1<'a> {
+  memory: Vec<u8>,
+  pointer: u32,
+  output: u8,
+  output_bits: u32,
+  input: u8,
+  input_bits: u32,
+  runtime: Runtime,
+  args: &'a Args
+}
+
+impl Memory1<'_> {
+  pub fn new<'a>(args: &'a Args) -> Result<Memory1<'a>, Error> {
+    let mut memory = Memory1 {
+      memory: allocate_bits(args)?,
+      pointer: 0,
+      output: 0,
+      output_bits: 0,
+      input: 0,
+      input_bits: 0,
+      runtime: Runtime::new(args)?,
+      args
+    };
+    memory.initialise()?;
+    Ok(memory)
+  }
+}
+
+impl Memory<bool> for Memory1<'_> {
+  fn args(&self) -> &Args {
+    self.args
+  }
+  fn size(&self) -> u32 {
+    self.args.memory_size.into()
+  }
+  fn null() -> bool {
+    false
+  }
+
+  // runtime
+
+  fn runtime(&self) -> &Runtime {
+    &self.runtime
+  }
+  fn runtime_mut(&mut self) -> &mut Runtime {
+    &mut self.runtime
+  }
+
+  // pointer
+
+  fn pointer(&self) -> u32 {
+    self.pointer
+  }
+  fn pointer_mut(&mut self) -> &mut u32 {
+    &mut self.pointer
+  }
+
+  // memory
+
+  fn value(&self, pointer: u32) -> bool {
+    self.memory[pointer as usize / 8] >> (pointer % 8) & 1 == 1
+  }
+  fn put_value(&mut self, pointer: u32, value: bool) {
+    let mask = 1 << (pointer % 8);
+    if value {
+      self.memory[pointer as usize / 8] |= mask;
+    } else {
+      self.memory[pointer as usize / 8] &= !mask;
+    }
+  }
+  fn value_is_null(&self, value: bool) -> bool {
+    !value
+  }
+
+  // + flips the bit rather than incrementing it, so bits never overflow, even with --no-overflows
+  #[inline(always)]
+  fn add_value(&self, pointer: u32, n: u32) -> Result<bool, Error> {
+    Ok(self.value(pointer) ^ (n % 2 == 1))
+  }
+  #[inline(always)]
+  fn sub_value(&self, pointer: u32, n: u32) -> Result<bool, Error> {
+    Ok(self.value(pointer) ^ (n % 2 == 1))
+  }
+
+  // conversions
+
+  fn value_to_u32(value: bool) -> u32 {
+    value as u32
+  }
+  fn value_from_u32(value: u32) -> bool {
+    value & 1 == 1
+  }
+  fn value_to_string(value: bool) -> String {
+    (value as u8).to_string()
+  }
+  fn value_to_char(value: bool) -> Result<char, Error> {
+    Ok(if value { '1' } else { '0' })
+  }
+  fn char_to_value(char: char) -> Result<bool, Error> {
+    Ok(char == '1')
+  }
+
+  // read / write
+
+  fn write(&mut self) -> Result<(), Error> {
+    if self.args.debug {
+      return self.write_debug();
+    }
+    self.output |= (self.current() as u8) << self.output_bits;
+    self.output_bits += 1;
+    if self.output_bits == 8 {
+      self.flush()?;
+    }
+    Ok(())
+  }
+
+  // the last byte is padded with null bits
+  fn flush(&mut self) -> Result<(), Error> {
+    if self.output_bits > 0 {
+      let char = self.output as char;
+      self.output = 0;
+      self.output_bits = 0;
+      self.emit(&char.to_string())?;
+    }
+    Ok(())
+  }
+
+  fn read(&mut self) -> Result<(), Error> {
+    if self.input_bits == 0 {
+      let char = self.next_char()?;
+      let value = char as u32;
+      if value > u16::MAX as u32 {
+        return Err(Error::Requires32Bits(char));
+      } else if value > u8::MAX as u32 {
+        return Err(Error::Requires16Bits(char));
+      }
+      self.input = value as u8;
+      self.input_bits = 8;
+    }
+    let value = self.input & 1 == 1;
+    self.input >>= 1;
+    self.input_bits -= 1;
+    self.set_value(self.pointer(), value);
+    Ok(())
+  }
+}
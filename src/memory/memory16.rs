@@ -1,19 +1,23 @@
-use crate::{Args, Error, Memory};
+use crate::{Args, Error, Memory, Runtime, allocate};
 
 #[derive(Debug)]
 pub struct Memory16<'a> {
   memory: Vec<u16>,
   pointer: u32,
+  runtime: Runtime,
   args: &'a Args
 }
 
 impl Memory16<'_> {
-  pub fn new<'a>(args: &'a Args) -> Memory16<'a> {
-    Memory16 {
-      memory: vec![0; u32::from(args.memory_size) as usize],
+  pub fn new<'a>(args: &'a Args) -> Result<Memory16<'a>, Error> {
+    let mut memory = Memory16 {
+      memory: allocate(args)?,
       pointer: 0,
+      runtime: Runtime::new(args)?,
       args
-    }
+    };
+    memory.initialise()?;
+    Ok(memory)
   }
 }
 
@@ -28,6 +32,15 @@ impl Memory<u16> for Memory16<'_> {
     0
   }
 
+  // runtime
+
+  fn runtime(&self) -> &Runtime {
+    &self.runtime
+  }
+  fn runtime_mut(&mut self) -> &mut Runtime {
+    &mut self.runtime
+  }
+
   // pointer
 
   fn pointer(&self) -> u32 {
@@ -42,13 +55,14 @@ impl Memory<u16> for Memory16<'_> {
   fn value(&self, pointer: u32) -> u16 {
     self.memory[pointer as usize]
   }
-  fn value_mut(&mut self, pointer: u32) -> &mut u16 {
-    &mut self.memory[pointer as usize]
+  fn put_value(&mut self, pointer: u32, value: u16) {
+    self.memory[pointer as usize] = value;
   }
   fn value_is_null(&self, value: u16) -> bool {
     value == 0
   }
 
+  #[inline(always)]
   fn add_value(&self, pointer: u32, n: u32) -> Result<u16, Error> {
     if self.args.no_overflows {
       if n > 0xFFFF {
@@ -63,6 +77,7 @@ impl Memory<u16> for Memory16<'_> {
       Ok(self.value(pointer).wrapping_add((n%0x10000) as u16))
     }
   }
+  #[inline(always)]
   fn sub_value(&self, pointer: u32, n: u32) -> Result<u16, Error> {
     if self.args.no_overflows {
       if n > 0xFFFF {
@@ -83,6 +98,9 @@ impl Memory<u16> for Memory16<'_> {
   fn value_to_u32(value: u16) -> u32 {
     value as u32
   }
+  fn value_from_u32(value: u32) -> u16 {
+    value as u16
+  }
   fn value_to_string(value: u16) -> String {
     value.to_string()
   }
@@ -1,19 +1,23 @@
-use crate::{Args, Error, Memory};
+use crate::{Args, Error, Memory, Runtime, allocate};
 
 #[derive(Debug)]
 pub struct Memory32<'a> {
   memory: Vec<u32>,
   pointer: u32,
+  runtime: Runtime,
   args: &'a Args
 }
 
 impl Memory32<'_> {
-  pub fn new<'a>(args: &'a Args) -> Memory32<'a> {
-    Memory32 {
-      memory: vec![0; u32::from(args.memory_size) as usize],
+  pub fn new<'a>(args: &'a Args) -> Result<Memory32<'a>, Error> {
+    let mut memory = Memory32 {
+      memory: allocate(args)?,
       pointer: 0,
+      runtime: Runtime::new(args)?,
       args
-    }
+    };
+    memory.initialise()?;
+    Ok(memory)
   }
 }
 
@@ -28,6 +32,15 @@ impl Memory<u32> for Memory32<'_> {
     0
   }
 
+  // runtime
+
+  fn runtime(&self) -> &Runtime {
+    &self.runtime
+  }
+  fn runtime_mut(&mut self) -> &mut Runtime {
+    &mut self.runtime
+  }
+
   // pointer
 
   fn pointer(&self) -> u32 {
@@ -42,13 +55,14 @@ impl Memory<u32> for Memory32<'_> {
   fn value(&self, pointer: u32) -> u32 {
     self.memory[pointer as usize]
   }
-  fn value_mut(&mut self, pointer: u32) -> &mut u32 {
-    &mut self.memory[pointer as usize]
+  fn put_value(&mut self, pointer: u32, value: u32) {
+    self.memory[pointer as usize] = value;
   }
   fn value_is_null(&self, value: u32) -> bool {
     value == 0
   }
 
+  #[inline(always)]
   fn add_value(&self, pointer: u32, n: u32) -> Result<u32, Error> {
     if self.args.no_overflows {
       match self.value(pointer).checked_add(n) {
@@ -59,6 +73,7 @@ impl Memory<u32> for Memory32<'_> {
       Ok(self.value(pointer).wrapping_add(n))
     }
   }
+  #[inline(always)]
   fn sub_value(&self, pointer: u32, n: u32) -> Result<u32, Error> {
     if self.args.no_overflows {
       match self.value(pointer).checked_sub(n) {
@@ -75,6 +90,9 @@ impl Memory<u32> for Memory32<'_> {
   fn value_to_u32(value: u32) -> u32 {
     value
   }
+  fn value_from_u32(value: u32) -> u32 {
+    value
+  }
   fn value_to_string(value: u32) -> String {
     value.to_string()
   }
@@ -1,19 +1,23 @@
-use crate::{Args, Error, Memory};
+use crate::{Args, Error, Memory, Runtime, allocate};
 
 #[derive(Debug)]
 pub struct Memory8<'a> {
   memory: Vec<u8>,
   pointer: u32,
+  runtime: Runtime,
   args: &'a Args
 }
 
 impl Memory8<'_> {
-  pub fn new<'a>(args: &'a Args) -> Memory8<'a> {
-    Memory8 {
-      memory: vec![0; u32::from(args.memory_size) as usize],
+  pub fn new<'a>(args: &'a Args) -> Result<Memory8<'a>, Error> {
+    let mut memory = Memory8 {
+      memory: allocate(args)?,
       pointer: 0,
+      runtime: Runtime::new(args)?,
       args
-    }
+    };
+    memory.initialise()?;
+    Ok(memory)
   }
 }
 
@@ -28,6 +32,15 @@ impl Memory<u8> for Memory8<'_> {
     0
   }
 
+  // runtime
+
+  fn runtime(&self) -> &Runtime {
+    &self.runtime
+  }
+  fn runtime_mut(&mut self) -> &mut Runtime {
+    &mut self.runtime
+  }
+
   // pointer
 
   fn pointer(&self) -> u32 {
@@ -42,13 +55,14 @@ impl Memory<u8> for Memory8<'_> {
   fn value(&self, pointer: u32) -> u8 {
     self.memory[pointer as usize]
   }
-  fn value_mut(&mut self, pointer: u32) -> &mut u8 {
-    &mut self.memory[pointer as usize]
+  fn put_value(&mut self, pointer: u32, value: u8) {
+    self.memory[pointer as usize] = value;
   }
   fn value_is_null(&self, value: u8) -> bool {
     value == 0
   }
 
+  #[inline(always)]
   fn add_value(&self, pointer: u32, n: u32) -> Result<u8, Error> {
     if self.args.no_overflows {
       if n > 255 {
@@ -63,6 +77,7 @@ impl Memory<u8> for Memory8<'_> {
       Ok(self.value(pointer).wrapping_add((n%256) as u8))
     }
   }
+  #[inline(always)]
   fn sub_value(&self, pointer: u32, n: u32) -> Result<u8, Error> {
     if self.args.no_overflows {
       if n > 255 {
@@ -83,6 +98,9 @@ impl Memory<u8> for Memory8<'_> {
   fn value_to_u32(value: u8) -> u32 {
     value as u32
   }
+  fn value_from_u32(value: u32) -> u8 {
+    value as u8
+  }
   fn value_to_string(value: u8) -> String {
     value.to_string()
   }
@@ -0,0 +1,136 @@
+use crate::{lex, optimise, parse, Edit, Error, Instruction, Move};
+
+fn edit(edit: Edit) -> String {
+  match edit {
+    Edit::Increment(n) => "+".repeat(n as usize),
+    Edit::Decrement(n) => "-".repeat(n as usize)
+  }
+}
+
+fn mov(mov: Move) -> String {
+  match mov {
+    Move::Right(n) => ">".repeat(n as usize),
+    Move::Left(n) => "<".repeat(n as usize)
+  }
+}
+
+fn back(mov: Move) -> Move {
+  match mov {
+    Move::Right(n) => Move::Left(n),
+    Move::Left(n) => Move::Right(n)
+  }
+}
+
+fn source(instructions: &[Instruction], bf: &mut String) {
+  for instruction in instructions {
+    match instruction {
+      Instruction::Loop(instructions, _)
+      | Instruction::InfiniteLoop(instructions, _) => {
+        bf.push('[');
+        source(instructions, bf);
+        bf.push(']');
+      }
+      Instruction::EditOtherLoop(m, e, _) => {
+        *bf += &format!("[-{}{}{}]", mov(*m), edit(*e), mov(back(*m)));
+      }
+      Instruction::MoveEdit(m, e, _) => *bf += &format!("{}{}", mov(*m), edit(*e)),
+      Instruction::EditOther(m, e, _) => *bf += &format!("{}{}{}", mov(*m), edit(*e), mov(back(*m))),
+      Instruction::Edit(e, _) => *bf += &edit(*e),
+      Instruction::Move(m, _) => *bf += &mov(*m),
+      Instruction::Clear(_) => *bf += "[-]",
+      Instruction::Write(_) => bf.push('.'),
+      Instruction::Read(_) => bf.push(','),
+      Instruction::Fork(_) => bf.push('Y'),
+      Instruction::Procedure(instructions, _) => {
+        bf.push('(');
+        source(instructions, bf);
+        bf.push(')');
+      }
+      Instruction::Call(_) => bf.push(':'),
+      Instruction::End(_) => bf.push('@'),
+      Instruction::Store(_) => bf.push('$'),
+      Instruction::Load(_) => bf.push('!'),
+      Instruction::Bitwise(bitwise, _) => *bf += &bitwise.to_string()
+    }
+  }
+}
+
+fn is_loop(instruction: Option<&Instruction>) -> bool {
+  matches!(instruction, Some(Instruction::Loop(_, _) | Instruction::InfiniteLoop(_, _) | Instruction::EditOtherLoop(_, _, _) | Instruction::Clear(_)))
+}
+
+// the current cell is null at the start of the program and right after a loop,
+// so a loop in either place never runs
+fn remove_dead_loops(instructions: Vec<Instruction>, start: bool) -> Vec<Instruction> {
+  let mut alive: Vec<Instruction> = vec![];
+  for instruction in instructions {
+    let dead = is_loop(Some(&instruction)) && ((start && alive.is_empty()) || is_loop(alive.last()));
+    if dead {
+      continue;
+    }
+    alive.push(match instruction {
+      Instruction::Loop(instructions, position) => Instruction::Loop(remove_dead_loops(instructions, false), position),
+      Instruction::InfiniteLoop(instructions, position) => Instruction::InfiniteLoop(remove_dead_loops(instructions, false), position),
+      instruction => instruction
+    });
+  }
+  alive
+}
+
+// removing a loop can bring cancelling commands together, so passes are repeated until nothing changes
+pub fn minify_source(program: &str) -> Result<String, Error> {
+  let mut minified = String::new();
+  source(&parse(&lex(program))?, &mut minified);
+  loop {
+    let instructions = remove_dead_loops(optimise(parse(&lex(&minified))?), true);
+    let mut shorter = String::new();
+    source(&instructions, &mut shorter);
+    if shorter.len() >= minified.len() {
+      return Ok(minified);
+    }
+    minified = shorter;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{verify, Args};
+  use clap::Clap;
+
+  #[test]
+  fn strips_comments_and_cancelling_commands() {
+    assert_eq!(minify_source("add two + - ++ then clear [-] and move >>< right").unwrap(), "++[-]>");
+    assert_eq!(minify_source("++[->+<]").unwrap(), "++[->+<]");
+  }
+
+  #[test]
+  fn removes_dead_loops() {
+    assert_eq!(minify_source("[.]++").unwrap(), "++");
+    assert_eq!(minify_source("+[-][.][>]").unwrap(), "+[-]");
+    assert_eq!(minify_source("+[>[-][+]<-]").unwrap(), "+[>[-]<-]");
+  }
+
+  #[test]
+  fn keeps_unbalanced_programs_invalid() {
+    assert!(matches!(minify_source("+]"), Err(Error::UnmatchedCloseLoop(_))));
+  }
+
+  #[test]
+  fn verifies_on_the_sample_input() {
+    let mut args = Args::parse_from(["brainfuck", "--input-string", "a"]);
+    args.read_input().unwrap();
+    assert_eq!(verify(",+.", ",+.", &args).unwrap(), None);
+    assert!(matches!(verify(",+.", ",++.", &args), Err(Error::MinifyMismatch)));
+    assert!(matches!(verify("+>+", "+", &args), Err(Error::MinifyMismatch)));
+  }
+
+  #[test]
+  fn reports_runs_that_could_not_be_compared() {
+    let args = Args::parse_from(["brainfuck"]);
+    assert_eq!(verify(",.", ",.", &args).unwrap(), Some("not verified (no input was given)"));
+    assert_eq!(verify("+[]", "+[]", &args).unwrap(), Some("not verified (step limit reached)"));
+    let args = Args::parse_from(["brainfuck", "--max-steps", "100"]);
+    assert_eq!(verify("+[>+<]", "+[>+<]", &args).unwrap(), Some("not verified (step limit reached)"));
+  }
+}
@@ -1,53 +1,89 @@
 use crate::{Instruction, Edit, Move};
 
-const UNEXPECTED_OVERFLOW: &'static str = "unexpected integer overflow";
+const UNEXPECTED_OVERFLOW: &str = "unexpected integer overflow";
+
+fn offset(mov: Move) -> i64 {
+  match mov {
+    Move::Right(n) => n as i64,
+    Move::Left(n) => -(n as i64)
+  }
+}
+
+// a loop whose body neither reads input, nor moves the pointer, nor touches
+// the current cell runs forever once it's been entered
+fn never_terminates(instructions: &[Instruction]) -> bool {
+  let mut current = 0;
+  for instruction in instructions {
+    match *instruction {
+      Instruction::Move(mov, _) => current += offset(mov),
+      Instruction::MoveEdit(mov, _, _) => {
+        current += offset(mov);
+        if current == 0 {
+          return false;
+        }
+      }
+      Instruction::EditOther(mov, _, _) => if current + offset(mov) == 0 {
+        return false;
+      }
+      Instruction::Edit(_, _)
+      | Instruction::Clear(_) => if current == 0 {
+        return false;
+      }
+      _ => return false
+    }
+  }
+  current == 0
+}
 
 fn optimise_first(instructions: Vec<Instruction>) -> Vec<Instruction> {
   let mut optimised = vec![];
   for instruction in instructions {
-    if let Instruction::Loop(inner_instructions) = instruction {
+    if let Instruction::Loop(inner_instructions, position) = instruction {
       let inner_optimised = optimise(inner_instructions);
       optimised.push(match inner_optimised[..] {
         /*[Instruction::Edit(Edit::Decrement(1)), Instruction::EditOther(mov, edit)]
         | [Instruction::EditOther(mov, edit), Instruction::Edit(Edit::Decrement(1))] => {
           Instruction::EditOtherLoop(mov, edit)
         }*/
-        [Instruction::Edit(Edit::Decrement(1))] => Instruction::Clear,
-        _ => Instruction::Loop(inner_optimised)
+        [Instruction::Edit(Edit::Decrement(1), _)] => Instruction::Clear(position),
+        _ if never_terminates(&inner_optimised) => Instruction::InfiniteLoop(inner_optimised, position),
+        _ => Instruction::Loop(inner_optimised, position)
       });
+    } else if let Instruction::Procedure(inner_instructions, position) = instruction {
+      optimised.push(Instruction::Procedure(optimise(inner_instructions), position));
     } else if let Some(previous) = optimised.last() {
       match (previous, instruction) {
-        (&Instruction::Edit(edit1), Instruction::Edit(edit2)) => match (edit1, edit2) {
+        (&Instruction::Edit(edit1, position), Instruction::Edit(edit2, _)) => match (edit1, edit2) {
           (Edit::Increment(incr1), Edit::Increment(incr2)) => {
-            *optimised.last_mut().unwrap() = Instruction::Edit(Edit::Increment(incr1.checked_add(incr2).expect(UNEXPECTED_OVERFLOW)));
+            *optimised.last_mut().unwrap() = Instruction::Edit(Edit::Increment(incr1.checked_add(incr2).expect(UNEXPECTED_OVERFLOW)), position);
           }
           (Edit::Decrement(decr1), Edit::Decrement(decr2)) => {
-            *optimised.last_mut().unwrap() = Instruction::Edit(Edit::Decrement(decr1.checked_add(decr2).expect(UNEXPECTED_OVERFLOW)));
+            *optimised.last_mut().unwrap() = Instruction::Edit(Edit::Decrement(decr1.checked_add(decr2).expect(UNEXPECTED_OVERFLOW)), position);
           }
           (Edit::Increment(incr), Edit::Decrement(decr))
           | (Edit::Decrement(decr), Edit::Increment(incr)) => {
             if incr > decr {
-              *optimised.last_mut().unwrap() = Instruction::Edit(Edit::Increment(incr.checked_sub(decr).expect(UNEXPECTED_OVERFLOW)));
+              *optimised.last_mut().unwrap() = Instruction::Edit(Edit::Increment(incr.checked_sub(decr).expect(UNEXPECTED_OVERFLOW)), position);
             } else if incr < decr {
-              *optimised.last_mut().unwrap() = Instruction::Edit(Edit::Decrement(decr.checked_sub(incr).expect(UNEXPECTED_OVERFLOW)));
+              *optimised.last_mut().unwrap() = Instruction::Edit(Edit::Decrement(decr.checked_sub(incr).expect(UNEXPECTED_OVERFLOW)), position);
             } else {
               optimised.pop();
             }
           }
         }
-        (&Instruction::Move(move1), Instruction::Move(move2)) => match (move1, move2) {
+        (&Instruction::Move(move1, position), Instruction::Move(move2, _)) => match (move1, move2) {
           (Move::Right(right1), Move::Right(right2)) => {
-            *optimised.last_mut().unwrap() = Instruction::Move(Move::Right(right1.checked_add(right2).expect(UNEXPECTED_OVERFLOW)));
+            *optimised.last_mut().unwrap() = Instruction::Move(Move::Right(right1.checked_add(right2).expect(UNEXPECTED_OVERFLOW)), position);
           }
           (Move::Left(left1), Move::Left(left2)) => {
-            *optimised.last_mut().unwrap() = Instruction::Move(Move::Left(left1.checked_add(left2).expect(UNEXPECTED_OVERFLOW)));
+            *optimised.last_mut().unwrap() = Instruction::Move(Move::Left(left1.checked_add(left2).expect(UNEXPECTED_OVERFLOW)), position);
           }
           (Move::Right(right), Move::Left(left))
           | (Move::Left(left), Move::Right(right)) => {
             if right > left {
-              *optimised.last_mut().unwrap() = Instruction::Move(Move::Right(right.checked_sub(left).expect(UNEXPECTED_OVERFLOW)));
+              *optimised.last_mut().unwrap() = Instruction::Move(Move::Right(right.checked_sub(left).expect(UNEXPECTED_OVERFLOW)), position);
             } else if right < left {
-              *optimised.last_mut().unwrap() = Instruction::Move(Move::Left(left.checked_sub(right).expect(UNEXPECTED_OVERFLOW)));
+              *optimised.last_mut().unwrap() = Instruction::Move(Move::Left(left.checked_sub(right).expect(UNEXPECTED_OVERFLOW)), position);
             } else {
               optimised.pop();
             }
@@ -67,25 +103,25 @@ fn optimise_second(instructions: Vec<Instruction>) -> Vec<Instruction> {
   for instruction in instructions {
     if let Some(previous) = optimised.last() {
       match (previous, instruction) {
-        (&Instruction::Move(mov), Instruction::Edit(edit)) => {
-          *optimised.last_mut().unwrap() = Instruction::MoveEdit(mov, edit);
+        (&Instruction::Move(mov, position), Instruction::Edit(edit, _)) => {
+          *optimised.last_mut().unwrap() = Instruction::MoveEdit(mov, edit, position);
         }
-        (&Instruction::MoveEdit(mov_before, edit), Instruction::Move(mov_after)) => match (mov_before, mov_after) {
+        (&Instruction::MoveEdit(mov_before, edit, position), Instruction::Move(mov_after, after)) => match (mov_before, mov_after) {
           (Move::Left(left), Move::Right(right)) => {
             if left == right {
-              *optimised.last_mut().unwrap() = Instruction::EditOther(Move::Left(left), edit);
+              *optimised.last_mut().unwrap() = Instruction::EditOther(Move::Left(left), edit, position);
             } else {
-              optimised.push(Instruction::Move(mov_after));
+              optimised.push(Instruction::Move(mov_after, after));
             }
           }
           (Move::Right(right), Move::Left(left)) => {
             if left == right {
-              *optimised.last_mut().unwrap() = Instruction::EditOther(Move::Right(right), edit);
+              *optimised.last_mut().unwrap() = Instruction::EditOther(Move::Right(right), edit, position);
             } else {
-              optimised.push(Instruction::Move(mov_after));
+              optimised.push(Instruction::Move(mov_after, after));
             }
           }
-          _ => optimised.push(Instruction::Move(mov_after))
+          _ => optimised.push(Instruction::Move(mov_after, after))
         }
         (_, instruction) => optimised.push(instruction)
       }
@@ -100,4 +136,67 @@ pub fn optimise(mut instructions: Vec<Instruction>) -> Vec<Instruction> {
   instructions = optimise_first(instructions);
   instructions = optimise_second(instructions);
   instructions
-}
\ No newline at end of file
+}
+
+fn without_infinite_loops(instruction: Instruction) -> Instruction {
+  match instruction {
+    Instruction::Loop(instructions, position)
+    | Instruction::InfiniteLoop(instructions, position) => Instruction::Loop(instructions.into_iter().map(without_infinite_loops).collect(), position),
+    instruction => instruction
+  }
+}
+
+// another Brainfork thread can change the cell a loop is testing, so no loop is known to never terminate
+pub fn optimise_threaded(instructions: Vec<Instruction>) -> Vec<Instruction> {
+  optimise(instructions).into_iter().map(without_infinite_loops).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{lex, parse};
+
+  fn infinite(program: &str, optimise: fn(Vec<Instruction>) -> Vec<Instruction>) -> bool {
+    match &optimise(parse(&lex(program)).unwrap())[..] {
+      [Instruction::Edit(_, _), Instruction::InfiniteLoop(_, _)] => true,
+      [Instruction::Edit(_, _), _] => false,
+      instructions => panic!("unexpected instructions {:?}", instructions)
+    }
+  }
+
+  #[test]
+  fn loops_that_never_change_the_current_cell() {
+    assert!(infinite("+[]", optimise));
+    assert!(infinite("+[>+<]", optimise));
+    assert!(infinite("+[>>-<+<]", optimise));
+    assert!(infinite("+[>-<<+>]", optimise));
+  }
+
+  #[test]
+  fn loops_that_can_end() {
+    assert!(!infinite("+[-]", optimise));
+    assert!(!infinite("+[>]", optimise));
+    assert!(!infinite("+[>+<-]", optimise));
+    assert!(!infinite("+[,]", optimise));
+    assert!(!infinite("+[>+<<->-]", optimise));
+    assert!(!infinite("+[.]", optimise));
+    assert!(!infinite("+[[-]]", optimise));
+  }
+
+  #[test]
+  fn nested_loops() {
+    let instructions = optimise(parse(&lex("+[>+[]<-]")).unwrap());
+    match &instructions[..] {
+      [Instruction::Edit(_, _), Instruction::Loop(body, _)] => assert!(matches!(body[..], [_, Instruction::InfiniteLoop(_, _), _])),
+      instructions => panic!("unexpected instructions {:?}", instructions)
+    }
+  }
+
+  #[test]
+  fn threads_can_end_any_loop() {
+    assert!(!infinite("+[]", optimise_threaded));
+    assert!(!infinite("+[>+<]", optimise_threaded));
+    let instructions = optimise_threaded(parse(&lex("+[>+[]<-]")).unwrap());
+    assert!(!format!("{:?}", instructions).contains("InfiniteLoop"));
+  }
+}
@@ -0,0 +1,352 @@
+use crate::{Error, Position};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Origin {
+  file: usize,
+  line: usize,
+  column: usize
+}
+
+type Text = Vec<(char, Origin)>;
+
+#[derive(Debug)]
+struct Macro {
+  parameters: Vec<String>,
+  body: Text
+}
+
+// where every character of the preprocessed program came from
+#[derive(Debug, Default)]
+pub struct SourceMap {
+  files: Vec<String>,
+  lines: Vec<Vec<Origin>>
+}
+
+impl SourceMap {
+  pub fn locate(&self, position: Position) -> Option<String> {
+    let origin = self.lines.get(position.line - 1)?.get(position.column - 1)?;
+    Some(format!("{}:{}:{}", self.files[origin.file], origin.line, origin.column))
+  }
+
+  // errors about a position in the preprocessed program also point to the original source
+  pub fn relocate(&self, err: Error) -> Error {
+    if let Error::Followed(err, failure) = err {
+      return Error::Followed(Box::new(self.relocate(*err)), failure);
+    }
+    let position = match &err {
+      Error::UnmatchedCloseLoop(position)
+      | Error::UnmatchedOpenLoop(position)
+      | Error::UnmatchedCloseProcedure(position)
+      | Error::UnmatchedOpenProcedure(position)
+      | Error::InfiniteLoop(position)
+      | Error::UndefinedProcedure(_, position) => *position,
+      _ => return err
+    };
+    match self.locate(position) {
+      None => err,
+      Some(location) => Error::Preprocessed(Box::new(err), location)
+    }
+  }
+}
+
+#[derive(Default)]
+struct Preprocessor {
+  map: SourceMap,
+  macros: HashMap<String, Macro>,
+  including: Vec<PathBuf>
+}
+
+fn location(map: &SourceMap, origin: Origin) -> String {
+  format!("{}:{}", map.files[origin.file], origin.line)
+}
+
+fn is_name(char: char) -> bool {
+  char.is_alphanumeric() || char == '_'
+}
+
+fn trim(text: &[(char, Origin)]) -> Text {
+  let start = text.iter().position(|(char, _)| !char.is_whitespace()).unwrap_or(text.len());
+  let end = text.iter().rposition(|(char, _)| !char.is_whitespace()).map_or(start, |end| end + 1);
+  text[start..end].to_vec()
+}
+
+// the index of the parenthesis closing the one at open
+fn closing(text: &[(char, Origin)], open: usize) -> Option<usize> {
+  let mut depth = 0;
+  for (i, (char, _)) in text.iter().enumerate().skip(open) {
+    match char {
+      '(' => depth += 1,
+      ')' => {
+        depth -= 1;
+        if depth == 0 {
+          return Some(i);
+        }
+      }
+      _ => {}
+    }
+  }
+  None
+}
+
+// arguments are separated by top level commas, so a read command has to be wrapped in parentheses
+fn arguments(text: &[(char, Origin)]) -> Vec<Text> {
+  let mut arguments = vec![vec![]];
+  let mut depth = 0;
+  for &(char, origin) in text {
+    match char {
+      ',' if depth == 0 => arguments.push(vec![]),
+      _ => {
+        match char {
+          '(' => depth += 1,
+          ')' => depth -= 1,
+          _ => {}
+        }
+        arguments.last_mut().unwrap().push((char, origin));
+      }
+    }
+  }
+  arguments.iter().map(|argument| trim(argument)).collect()
+}
+
+impl Preprocessor {
+  fn file(&mut self, path: &Path, content: &str) -> Result<Text, Error> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if self.including.contains(&canonical) {
+      let chain: Vec<String> = self.including.iter().chain([&canonical]).map(|path| path.display().to_string()).collect();
+      return Err(Error::IncludeCycle(chain.join(" -> ")));
+    }
+    self.including.push(canonical);
+    self.map.files.push(path.display().to_string());
+    let file = self.map.files.len() - 1;
+    let mut output = vec![];
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+      let text: Text = line.chars().enumerate().map(|(column, char)| (char, Origin { file, line: index + 1, column: column + 1 })).collect();
+      let trimmed = line.trim_start();
+      let indent = text.len() - trimmed.chars().count();
+      if let Some(definition) = trimmed.strip_prefix("define ") {
+        self.define(&text[indent + 7..], definition, text[indent].1)?;
+        output.push(('\n', text[text.len() - 1].1));
+      } else if let Some(include) = trimmed.strip_prefix("include ") {
+        let name = include.trim().strip_prefix('"').and_then(|name| name.strip_suffix('"'));
+        let name = match name {
+          None => return Err(Error::InvalidDirective(location(&self.map, text[indent].1))),
+          Some(name) => name
+        };
+        let included = path.parent().unwrap_or_else(|| Path::new("")).join(name);
+        let content = match fs::read_to_string(&included) {
+          Err(_) => return Err(Error::ReadFileFail(included.display().to_string())),
+          Ok(content) => content
+        };
+        output.extend(self.file(&included, &content)?);
+        output.push(('\n', text[text.len() - 1].1));
+      } else {
+        output.extend(self.expand(&text, 0)?);
+      }
+    }
+    self.including.pop();
+    Ok(output)
+  }
+
+  // define name body or define name(first, second) body, on a single line
+  fn define(&mut self, text: &[(char, Origin)], definition: &str, origin: Origin) -> Result<(), Error> {
+    let invalid = || Error::InvalidDirective(location(&self.map, origin));
+    let name: String = definition.chars().take_while(|&char| is_name(char)).collect();
+    if name.is_empty() {
+      return Err(invalid());
+    }
+    let rest = &text[name.chars().count()..];
+    let (parameters, body) = if rest.first().map(|(char, _)| *char) == Some('(') {
+      let end = closing(rest, 0).ok_or_else(invalid)?;
+      let parameters: Vec<String> = arguments(&rest[1..end]).iter().map(|parameter| parameter.iter().map(|(char, _)| char).collect()).collect();
+      if parameters.iter().any(|parameter: &String| parameter.is_empty() || !parameter.chars().all(is_name)) {
+        return Err(invalid());
+      }
+      (parameters, &rest[end + 1..])
+    } else {
+      (vec![], rest)
+    };
+    self.macros.insert(name, Macro { parameters, body: trim(body) });
+    Ok(())
+  }
+
+  fn substitute(&self, body: &[(char, Origin)], parameters: &[String], arguments: &[Text]) -> Text {
+    let mut output = vec![];
+    let mut i = 0;
+    while i < body.len() {
+      if is_name(body[i].0) {
+        let end = body[i..].iter().position(|(char, _)| !is_name(*char)).map_or(body.len(), |end| i + end);
+        let word: String = body[i..end].iter().map(|(char, _)| char).collect();
+        match parameters.iter().position(|parameter| *parameter == word) {
+          Some(index) => output.extend(arguments[index].iter().copied()),
+          None => output.extend(body[i..end].iter().copied())
+        }
+        i = end;
+      } else {
+        output.push(body[i]);
+        i += 1;
+      }
+    }
+    output
+  }
+
+  // expands macros, (groups) and repetitions like +*10 or (>+)*3
+  fn expand(&self, text: &[(char, Origin)], depth: usize) -> Result<Text, Error> {
+    let mut output: Text = vec![];
+    // the last command, group or macro expansion, which a repetition applies to
+    let mut unit: Option<usize> = None;
+    let mut i = 0;
+    while i < text.len() {
+      let (char, origin) = text[i];
+      if is_name(char) && !char.is_ascii_digit() {
+        let end = text[i..].iter().position(|(char, _)| !is_name(*char)).map_or(text.len(), |end| i + end);
+        let name: String = text[i..end].iter().map(|(char, _)| char).collect();
+        match self.macros.get(&name) {
+          None => {
+            output.extend(text[i..end].iter().copied());
+            unit = None;
+            i = end;
+          }
+          Some(definition) => {
+            if depth >= MAX_EXPANSION_DEPTH {
+              return Err(Error::MacroRecursion(name, location(&self.map, origin)));
+            }
+            let (arguments, next) = if definition.parameters.is_empty() {
+              (vec![], end)
+            } else {
+              match text.get(end).map(|(char, _)| *char) {
+                Some('(') => match closing(text, end) {
+                  None => return Err(Error::InvalidDirective(location(&self.map, origin))),
+                  Some(close) => (arguments(&text[end + 1..close]), close + 1)
+                }
+                _ => (vec![], end)
+              }
+            };
+            let given = if arguments.len() == 1 && arguments[0].is_empty() { 0 } else { arguments.len() };
+            if given != definition.parameters.len() {
+              return Err(Error::MacroArguments(name, definition.parameters.len(), given, location(&self.map, origin)));
+            }
+            let expansion = self.expand(&self.substitute(&definition.body, &definition.parameters, &arguments), depth + 1)?;
+            unit = Some(output.len());
+            output.extend(expansion);
+            i = next;
+          }
+        }
+      } else if char == '(' {
+        match closing(text, i) {
+          None => {
+            output.push((char, origin));
+            unit = None;
+            i += 1;
+          }
+          Some(close) => {
+            let group = self.expand(&text[i + 1..close], depth)?;
+            unit = Some(output.len());
+            output.extend(group);
+            i = close + 1;
+          }
+        }
+      } else if char == '*' && unit.is_some() && text.get(i + 1).is_some_and(|(char, _)| char.is_ascii_digit()) {
+        let end = text[i + 1..].iter().position(|(char, _)| !char.is_ascii_digit()).map_or(text.len(), |end| i + 1 + end);
+        let count: String = text[i + 1..end].iter().map(|(char, _)| char).collect();
+        let count: usize = match count.parse() {
+          Err(_) => return Err(Error::InvalidDirective(location(&self.map, origin))),
+          Ok(count) => count
+        };
+        let start = unit.unwrap();
+        let repeated = output[start..].to_vec();
+        output.truncate(start);
+        for _ in 0..count {
+          output.extend(repeated.iter().copied());
+        }
+        unit = None;
+        i = end;
+      } else {
+        output.push((char, origin));
+        unit = if "+-<>.,[]".contains(char) { Some(output.len() - 1) } else { None };
+        i += 1;
+      }
+    }
+    Ok(output)
+  }
+}
+
+pub fn preprocess(file: Option<&str>, content: &str) -> Result<(String, SourceMap), Error> {
+  let mut preprocessor = Preprocessor::default();
+  let path = Path::new(file.unwrap_or("<inline>"));
+  let output = preprocessor.file(path, content)?;
+  let mut map = preprocessor.map;
+  let mut program = String::new();
+  let mut line = vec![];
+  for (char, origin) in output {
+    program.push(char);
+    line.push(origin);
+    if char == '\n' {
+      map.lines.push(std::mem::take(&mut line));
+    }
+  }
+  map.lines.push(line);
+  Ok((program, map))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn expand(content: &str) -> Result<String, Error> {
+    preprocess(None, content).map(|(program, _)| program)
+  }
+
+  #[test]
+  fn repetitions() {
+    assert_eq!(expand("+*3 (>+)*2 -*0.\n").unwrap(), "+++ >+>+ .\n");
+    assert_eq!(expand("x*3 (unclosed").unwrap(), "x*3 (unclosed");
+  }
+
+  #[test]
+  fn macros() {
+    assert_eq!(expand("define clear [-]\nclear >clear*2").unwrap(), "\n[-] >[-][-]");
+    assert_eq!(expand("define move(there, back) [-there+back]\nmove(>>, <<)").unwrap(), "\n[->>+<<]");
+    assert_eq!(expand("define right >\ndefine twice(x) x x\ntwice(right)").unwrap(), "\n\n> >");
+  }
+
+  #[test]
+  fn invalid_macros() {
+    assert!(matches!(expand("define loop [loop]\nloop"), Err(Error::MacroRecursion(name, _)) if name == "loop"));
+    assert!(matches!(expand("define move(a, b) a b\nmove(>)"), Err(Error::MacroArguments(_, 2, 1, _))));
+    assert!(matches!(expand("define (x) x"), Err(Error::InvalidDirective(_))));
+    assert!(matches!(expand("define f(x, +) x"), Err(Error::InvalidDirective(_))));
+    assert!(matches!(expand("include nothing.bf"), Err(Error::InvalidDirective(location)) if location == "<inline>:1"));
+  }
+
+  #[test]
+  fn source_map() {
+    let (program, map) = preprocess(None, "define clear [-]\n+clear [").unwrap();
+    assert_eq!(program, "\n+[-] [");
+    assert_eq!(map.locate(Position { line: 2, column: 2 }).as_deref(), Some("<inline>:1:14"));
+    assert_eq!(map.locate(Position { line: 2, column: 1 }).as_deref(), Some("<inline>:2:1"));
+    assert_eq!(map.locate(Position { line: 3, column: 1 }), None);
+    let err = map.relocate(Error::UnmatchedOpenLoop(Position { line: 2, column: 6 }));
+    assert!(matches!(err, Error::Preprocessed(_, location) if location == "<inline>:2:8"));
+  }
+
+  #[test]
+  fn includes() {
+    let dir = std::env::temp_dir().join(format!("brainfuck-preprocess-{}", std::process::id()));
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("lib/util.bf"), "define clear [-]\n").unwrap();
+    fs::write(dir.join("cycle.bf"), "include \"cycle.bf\"\n").unwrap();
+    let main = dir.join("main.bf");
+    let main = main.to_str().unwrap();
+    let (program, map) = preprocess(Some(main), "include \"lib/util.bf\"\n+clear").unwrap();
+    assert_eq!(program, "\n\n+[-]");
+    assert!(map.locate(Position { line: 3, column: 2 }).unwrap().ends_with("util.bf:1:14"));
+    assert!(matches!(preprocess(Some(main), "include \"missing.bf\""), Err(Error::ReadFileFail(_))));
+    assert!(matches!(preprocess(Some(main), "include \"cycle.bf\""), Err(Error::IncludeCycle(_))));
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,93 @@
+use crate::{Instruction, Position};
+use std::collections::HashMap;
+use colored::Colorize;
+
+const HOT_INSTRUCTIONS: usize = 10;
+const HOT_LOOPS: usize = 10;
+
+#[derive(Debug, Default, Clone)]
+pub struct InstructionProfile {
+  pub instruction: String,
+  pub steps: u64
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LoopProfile {
+  pub entries: u64,
+  pub iterations: u64
+}
+
+// counts are keyed by where the instruction is in the source, so the report points at hot spots
+#[derive(Debug, Default)]
+pub struct Profile {
+  instructions: HashMap<Position, InstructionProfile>,
+  loops: HashMap<Position, LoopProfile>
+}
+
+impl Profile {
+  pub fn count(&mut self, instruction: &Instruction) {
+    let position = instruction.position();
+    self.instructions.entry(position).or_insert_with(|| InstructionProfile {
+      instruction: instruction.to_string(),
+      steps: 0
+    }).steps += 1;
+    if let Instruction::Loop(_, _) | Instruction::InfiniteLoop(_, _) = instruction {
+      self.loops.entry(position).or_default().entries += 1;
+    }
+  }
+
+  pub fn count_iteration(&mut self, position: Position) {
+    self.loops.entry(position).or_default().iterations += 1;
+  }
+
+  pub fn hot_instructions(&self) -> Vec<(Position, InstructionProfile)> {
+    let mut instructions: Vec<(Position, InstructionProfile)> = self.instructions.iter()
+      .map(|(position, profile)| (*position, profile.clone()))
+      .collect();
+    instructions.sort_by(|(position1, profile1), (position2, profile2)| {
+      profile2.steps.cmp(&profile1.steps).then(position1.cmp(position2))
+    });
+    instructions.truncate(HOT_INSTRUCTIONS);
+    instructions
+  }
+
+  pub fn hot_loops(&self) -> Vec<(Position, LoopProfile)> {
+    let mut loops: Vec<(Position, LoopProfile)> = self.loops.iter()
+      .map(|(position, profile)| (*position, *profile))
+      .collect();
+    loops.sort_by(|(position1, profile1), (position2, profile2)| {
+      profile2.iterations.cmp(&profile1.iterations).then(position1.cmp(position2))
+    });
+    loops.truncate(HOT_LOOPS);
+    loops
+  }
+
+  // on stderr so that it doesn't get mixed up with the program's output
+  pub fn report(&self, steps: u64) {
+    eprintln!("{} {} steps", "profile:".green(), steps.to_string().green());
+    let hot_instructions = self.hot_instructions();
+    if !hot_instructions.is_empty() {
+      eprintln!("{}", "hot instructions:".green());
+      for (i, (position, profile)) in hot_instructions.iter().enumerate() {
+        eprintln!("  {:>2}. {} at {}: {} steps",
+          i + 1,
+          profile.instruction,
+          position.to_string().yellow(),
+          profile.steps.to_string().green()
+        );
+      }
+    }
+    let hot_loops = self.hot_loops();
+    if !hot_loops.is_empty() {
+      eprintln!("{}", "hot loops:".green());
+      for (i, (position, profile)) in hot_loops.iter().enumerate() {
+        eprintln!("  {:>2}. loop at {}: {} iterations over {} entries",
+          i + 1,
+          position.to_string().yellow(),
+          profile.iterations.to_string().green(),
+          profile.entries.to_string().green()
+        );
+      }
+    }
+  }
+}
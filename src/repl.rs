@@ -0,0 +1,159 @@
+use crate::{dump, lex_dialect, optimise, optimise_threaded, parse, run, tape, TAPE_RADIUS, Args, Dialect, Error, Memory, Memory1, Memory16, Memory32, Memory8};
+use std::io::{stdin, stdout, Write};
+use colored::Colorize;
+use std::fs;
+
+const HELP: &str = "\
+commands:
+  :reset                Clear every cell and move the pointer back to its initial position
+  :dump [format]        Print the used memory as hex, decimal, json or raw (default: hex)
+  :cellsize <8|16|32>   Change the size of cells, keeping the tape if its values fit
+  :load <file>          Run a Brainfuck file on the current tape
+  :help                 Print this list of commands
+  :quit                 Leave the REPL
+anything else is run as Brainfuck, lines are buffered until every loop and procedure is closed";
+
+enum Exit {
+  Quit,
+  CellSize(String, Vec<u32>, u32)
+}
+
+// runs a chunk of code on the persistent tape, reporting errors without ending the session
+fn eval<T, M: Memory<T>>(memory: &mut M, dialect: &Dialect, code: &str) {
+  let tokens = lex_dialect(code, dialect);
+  let result = parse(&tokens).and_then(|mut instructions| {
+    if !memory.args().unoptimised {
+      instructions = if memory.args().dialect == "brainfork" { optimise_threaded(instructions) } else { optimise(instructions) };
+    }
+    memory.runtime_mut().restart();
+    run(&instructions, memory)
+  });
+  let output = memory.runtime().output;
+  stdout().flush().ok();
+  if output > 0 {
+    println!();
+  }
+  if let Err(err) = result {
+    println!("{} {}", err.label().trim_start().red(), err.message());
+  }
+  println!("{} {}", "repl:".green(), tape(memory, TAPE_RADIUS));
+}
+
+fn reset<T, M: Memory<T>>(memory: &mut M) {
+  for pointer in 0..memory.size() {
+    memory.put_value(pointer, M::null());
+  }
+  *memory.pointer_mut() = memory.args().init_pointer;
+  memory.runtime_mut().restart();
+}
+
+fn load<T, M: Memory<T>>(memory: &mut M, cells: &[u32], pointer: u32) {
+  for (index, &value) in cells.iter().enumerate().take(memory.size() as usize) {
+    memory.put_value(index as u32, M::value_from_u32(value));
+  }
+  *memory.pointer_mut() = pointer;
+}
+
+fn cell_size<T, M: Memory<T>>(memory: &M, argument: Option<&str>) -> Result<Exit, String> {
+  if memory.args().dialect == "boolfuck" {
+    return Err(String::from("Boolfuck cells are always single bits"));
+  }
+  let bits: u32 = match argument {
+    Some(size @ ("8" | "16" | "32")) => size.parse().unwrap(),
+    _ => return Err(String::from("expected a cell size of 8, 16 or 32"))
+  };
+  let cells = memory.cells();
+  let max = u32::MAX >> (32 - bits);
+  match cells.iter().position(|&value| value > max) {
+    Some(pointer) => Err(Error::InitValueTooLarge(pointer as u32, cells[pointer], bits).message()),
+    None => Ok(Exit::CellSize(bits.to_string(), cells, memory.pointer()))
+  }
+}
+
+fn session<T, M: Memory<T>>(memory: &mut M) -> Result<Exit, Error> {
+  let dialect = Dialect::from_arg(&memory.args().dialect)?;
+  let mut buffer = String::new();
+  loop {
+    print!("{} ", if buffer.is_empty() { "bf>" } else { "..." }.green());
+    stdout().flush().ok();
+    let mut line = String::new();
+    match stdin().read_line(&mut line) {
+      Err(_) => return Err(Error::ReadInputFail),
+      Ok(0) => return Ok(Exit::Quit),
+      Ok(_) => {}
+    }
+    if buffer.is_empty() && line.trim_start().starts_with(':') {
+      let mut words = line.split_whitespace();
+      let command = words.next().unwrap_or_default();
+      let argument = words.next();
+      match command {
+        ":reset" => {
+          reset(memory);
+          println!("{} {}", "repl:".green(), tape(memory, TAPE_RADIUS));
+        }
+        ":dump" => {
+          stdout().write_all(&dump(memory, argument.unwrap_or("hex"))).ok();
+        }
+        ":cellsize" => match cell_size(memory, argument) {
+          Err(message) => println!("{} {}", "repl:".red(), message),
+          Ok(exit) => return Ok(exit)
+        }
+        ":load" => match argument {
+          None => println!("{} expected a file", "repl:".red()),
+          Some(file) => match fs::read_to_string(file) {
+            Err(_) => println!("{} {}", "repl:".red(), Error::ReadFileFail(file.to_string()).message()),
+            Ok(content) => eval(memory, &dialect, &content)
+          }
+        }
+        ":help" | ":h" => println!("{}", HELP),
+        ":quit" | ":q" => return Ok(Exit::Quit),
+        _ => println!("{} unknown command '{}', type ':help' for a list of commands", "repl:".red(), command)
+      }
+      continue;
+    }
+    buffer += &line;
+    // an unclosed loop or procedure waits for more lines, anything else is run or reported right away
+    if let Err(Error::UnmatchedOpenLoop(_) | Error::UnmatchedOpenProcedure(_)) = parse(&lex_dialect(&buffer, &dialect)) {
+      continue;
+    }
+    eval(memory, &dialect, &buffer);
+    buffer.clear();
+  }
+}
+
+pub fn repl(args: &Args) -> Result<(), Error> {
+  let mut args = args.clone();
+  let mut saved: Option<(Vec<u32>, u32)> = None;
+  println!("{} type ':help' for a list of commands", "repl:".green());
+  loop {
+    let exit = if args.dialect == "boolfuck" {
+      session(&mut Memory1::new(&args)?)?
+    } else if args.cell_size == "8" {
+      let mut memory = Memory8::new(&args)?;
+      if let Some((cells, pointer)) = &saved {
+        load(&mut memory, cells, *pointer);
+      }
+      session(&mut memory)?
+    } else if args.cell_size == "16" {
+      let mut memory = Memory16::new(&args)?;
+      if let Some((cells, pointer)) = &saved {
+        load(&mut memory, cells, *pointer);
+      }
+      session(&mut memory)?
+    } else {
+      let mut memory = Memory32::new(&args)?;
+      if let Some((cells, pointer)) = &saved {
+        load(&mut memory, cells, *pointer);
+      }
+      session(&mut memory)?
+    };
+    match exit {
+      Exit::Quit => return Ok(()),
+      Exit::CellSize(size, cells, pointer) => {
+        println!("{} cells are now {} bits", "repl:".green(), size.green());
+        args.cell_size = size;
+        saved = Some((cells, pointer));
+      }
+    }
+  }
+}
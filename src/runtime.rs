@@ -0,0 +1,122 @@
+use crate::{Args, Error, Hit, History, Profile, Trace, Watchpoint};
+use colored::Colorize;
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, VecDeque};
+
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+const MAX_CALL_DEPTH: usize = 10000;
+
+#[derive(Debug, Default)]
+pub struct Runtime {
+  // off when nothing below counts steps, records changes or watches cells, so a plain run
+  // only pays for a branch per instruction, steps aren't counted then
+  pub instrumented: bool,
+  pub steps: u64,
+  pub max_steps: Option<u64>,
+  pub output: u64,
+  pub silent: bool,
+  pub captured: Option<String>,
+  pub input: Option<VecDeque<char>>,
+  pub max_output: Option<u64>,
+  pub timeout: Option<Duration>,
+  pub start: Option<Instant>,
+  pub profile: Option<Profile>,
+  pub trace: Option<Trace>,
+  pub history: Option<History>,
+  pub watchpoints: Vec<Watchpoint>,
+  pub hit: Option<Hit>,
+  // pbrain procedures by number, where their bodies are in the program and where each call returns to
+  pub procedures: HashMap<u32, Vec<usize>>,
+  pub calls: Vec<Vec<usize>>,
+  // the storage of Extended Brainfuck Type I
+  pub register: u32
+}
+
+impl Runtime {
+  pub fn new(args: &Args) -> Result<Runtime, Error> {
+    let timeout = match args.timeout {
+      None => None,
+      Some(seconds) => match Duration::try_from_secs_f64(seconds) {
+        Err(_) => return Err(Error::InvalidTimeout(seconds)),
+        Ok(timeout) => Some(timeout)
+      }
+    };
+    let profile = if args.profile { Some(Profile::default()) } else { None };
+    let trace = Trace::new(args)?;
+    let history = if args.debugger { Some(History::default()) } else { None };
+    Ok(Runtime {
+      instrumented: args.max_steps.is_some() || timeout.is_some() || profile.is_some() || trace.is_some() || history.is_some() || !args.watch.is_empty(),
+      steps: 0,
+      max_steps: args.max_steps,
+      output: 0,
+      silent: false,
+      captured: None,
+      input: args.program_input.as_ref().map(|input| input.chars().collect()),
+      max_output: args.max_output,
+      timeout,
+      start: Some(Instant::now()),
+      profile,
+      trace,
+      history,
+      watchpoints: args.watch.iter().map(|watchpoint| Watchpoint { pause: args.debugger, ..*watchpoint }).collect(),
+      hit: None,
+      procedures: HashMap::new(),
+      calls: vec![],
+      register: 0
+    })
+  }
+
+  // step and time limits apply to each run rather than to the whole session,
+  // procedures point into the program that defined them so they don't outlive it
+  pub fn restart(&mut self) {
+    self.steps = 0;
+    self.output = 0;
+    self.start = Some(Instant::now());
+    self.procedures.clear();
+    self.calls.clear();
+  }
+
+  pub fn call(&mut self, back: Vec<usize>) -> Result<(), Error> {
+    if self.calls.len() == MAX_CALL_DEPTH {
+      return Err(Error::StackOverflow(MAX_CALL_DEPTH));
+    }
+    self.calls.push(back);
+    Ok(())
+  }
+
+  pub fn tick(&mut self) -> Result<(), Error> {
+    if let Some(max_steps) = self.max_steps {
+      if self.steps >= max_steps {
+        return Err(Error::StepLimitReached(self.steps));
+      }
+    }
+    self.steps += 1;
+    if let (Some(timeout), Some(start)) = (self.timeout, self.start) {
+      if self.steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && start.elapsed() > timeout {
+        return Err(Error::TimedOut(timeout.as_secs_f64(), self.steps));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn count_output(&mut self, bytes: usize) -> Result<(), Error> {
+    self.output += bytes as u64;
+    match self.max_output {
+      Some(max_output) if self.output > max_output => Err(Error::OutputLimitReached(max_output)),
+      _ => Ok(())
+    }
+  }
+
+  pub fn watch(&mut self, pointer: u32, previous: u32, value: u32) {
+    for watchpoint in &self.watchpoints {
+      if watchpoint.matches(pointer, previous, value) {
+        let hit = Hit { watchpoint: *watchpoint, step: self.steps, pointer, previous, value };
+        if !watchpoint.pause {
+          eprintln!("{} {}", "watch:".green(), hit);
+        } else if self.hit.is_none() {
+          self.hit = Some(hit);
+        }
+      }
+    }
+  }
+}
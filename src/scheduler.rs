@@ -0,0 +1,100 @@
+use crate::{Debugger, Error, Instruction, Memory};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, Scope};
+
+const MAX_THREADS: usize = 1024;
+
+// threads share the tape, each one has its own pointer and place in the program
+struct Thread<'a> {
+  cursor: Debugger<'a>,
+  pointer: u32
+}
+
+// runs one instruction of the thread, returning the child when it forks:
+// the parent's cell is cleared, the child starts on the next cell set to 1
+fn turn<'a, T, M: Memory<T>>(thread: &mut Thread<'a>, memory: &mut M) -> Result<Option<Thread<'a>>, Error> {
+  *memory.pointer_mut() = thread.pointer;
+  let forks = matches!(thread.cursor.current(), Some(Instruction::Fork(_)));
+  thread.cursor.step(memory)?;
+  thread.pointer = memory.pointer();
+  if !forks {
+    return Ok(None);
+  }
+  let pointer = memory.add_pointer(1)?;
+  memory.set_value(pointer, M::value_from_u32(1));
+  Ok(Some(Thread { cursor: thread.cursor.fork(), pointer }))
+}
+
+// every thread runs one instruction per round, in the order they were forked,
+// so a program always runs the same way
+fn round_robin<T, M: Memory<T>>(instructions: &[Instruction], memory: &mut M) -> Result<(), Error> {
+  let mut threads = vec![Thread { cursor: Debugger::new(instructions, 0), pointer: memory.pointer() }];
+  while !threads.is_empty() {
+    // forked threads start on the next round
+    for i in 0..threads.len() {
+      if !threads[i].cursor.is_finished() {
+        if let Some(child) = turn(&mut threads[i], memory)? {
+          if threads.len() == MAX_THREADS {
+            return Err(Error::TooManyThreads(MAX_THREADS));
+          }
+          threads.push(child);
+        }
+      }
+    }
+    threads.retain(|thread| !thread.cursor.is_finished());
+  }
+  Ok(())
+}
+
+struct Shared<'m, M> {
+  memory: Mutex<&'m mut M>,
+  error: Mutex<Option<Error>>,
+  threads: AtomicUsize
+}
+
+fn spawn<'scope, 'env: 'scope, 'm, T, M: Memory<T>>(scope: &'scope Scope<'scope, 'env>, shared: &'env Shared<'m, M>, mut thread: Thread<'env>) {
+  scope.spawn(move || {
+    while !thread.cursor.is_finished() {
+      let result = {
+        let mut memory = shared.memory.lock().unwrap();
+        if shared.error.lock().unwrap().is_some() {
+          break;
+        }
+        turn(&mut thread, &mut **memory)
+      };
+      let err = match result {
+        Ok(None) => continue,
+        Ok(Some(_)) if shared.threads.fetch_add(1, Ordering::SeqCst) == MAX_THREADS => Error::TooManyThreads(MAX_THREADS),
+        Ok(Some(child)) => {
+          spawn(scope, shared, child);
+          continue;
+        }
+        Err(err) => err
+      };
+      shared.error.lock().unwrap().get_or_insert(err);
+      break;
+    }
+    shared.threads.fetch_sub(1, Ordering::SeqCst);
+  });
+}
+
+// every thread runs on an OS thread, taking the tape for one instruction at a time,
+// so the order they run in is up to the OS
+fn os_threads<T, M: Memory<T>>(instructions: &[Instruction], memory: &mut M) -> Result<(), Error> {
+  let pointer = memory.pointer();
+  let shared = Shared { memory: Mutex::new(memory), error: Mutex::new(None), threads: AtomicUsize::new(1) };
+  thread::scope(|scope| spawn(scope, &shared, Thread { cursor: Debugger::new(instructions, 0), pointer }));
+  match shared.error.into_inner().unwrap() {
+    Some(err) => Err(err),
+    None => Ok(())
+  }
+}
+
+pub fn run_threads<T, M: Memory<T>>(instructions: &[Instruction], memory: &mut M) -> Result<(), Error> {
+  if memory.args().scheduler == "os" {
+    os_threads(instructions, memory)
+  } else {
+    round_robin(instructions, memory)
+  }
+}
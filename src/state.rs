@@ -0,0 +1,310 @@
+use crate::{Args, Error, Memory};
+use std::convert::TryInto;
+use std::fs;
+
+const MAGIC: &[u8; 8] = b"BFSTATE\0";
+const VERSION: u32 = 1;
+
+const WRAP_AROUND: u8 = 1;
+const NO_OVERFLOWS: u8 = 2;
+const UNOPTIMISED: u8 = 4;
+const DEBUG: u8 = 8;
+const PREPROCESS: u8 = 16;
+
+// FNV-1a, so that hashes stay the same across Rust versions
+pub fn hash(program: &str) -> u64 {
+  program.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+    (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+  })
+}
+
+// threads, procedures, call stacks, the register and bits waiting to be written aren't part of the state,
+// and neither is a tape of bits
+pub fn saves(dialect: &str) -> bool {
+  !matches!(dialect, "brainfork" | "pbrain" | "extended" | "boolfuck")
+}
+
+#[derive(Debug, PartialEq)]
+pub struct State {
+  pub file: String,
+  pub source: String,
+  pub hash: u64,
+  pub cell_size: u8,
+  pub memory_size: u32,
+  pub flags: u8,
+  pub steps: u64,
+  pub pointer: u32,
+  pub path: Vec<usize>,
+  pub input: Option<String>,
+  pub cells: Vec<u32>,
+  pub dialect: String
+}
+
+impl State {
+  pub fn capture<T, M: Memory<T>>(memory: &M, hash: u64, path: &[usize]) -> Result<State, Error> {
+    let args = memory.args();
+    if !saves(&args.dialect) {
+      return Err(Error::UnsupportedDialect(args.dialect.clone(), "saved execution states"));
+    }
+    let mut cells = memory.cells();
+    while cells.last() == Some(&0) {
+      cells.pop();
+    }
+    Ok(State {
+      file: args.file.clone().unwrap_or_default(),
+      source: args.inline.clone().unwrap_or_default(),
+      hash,
+      cell_size: args.cell_size.parse().unwrap_or(8),
+      memory_size: args.memory_size.get(),
+      flags: [(args.wrap_around, WRAP_AROUND), (args.no_overflows, NO_OVERFLOWS), (args.unoptimised, UNOPTIMISED), (args.debug, DEBUG), (args.preprocess, PREPROCESS)]
+        .iter().filter(|(set, _)| *set).fold(0, |flags, (_, flag)| flags | flag),
+      steps: memory.runtime().steps,
+      pointer: memory.pointer(),
+      path: path.to_vec(),
+      input: memory.runtime().input.as_ref().map(|input| input.iter().collect()),
+      cells,
+      dialect: args.dialect.clone()
+    })
+  }
+
+  pub fn restore<T, M: Memory<T>>(&self, memory: &mut M) -> Result<(), Error> {
+    if self.pointer >= memory.size() || self.cells.len() > memory.size() as usize {
+      return Err(Error::InvalidState(self.file.clone()));
+    }
+    for (pointer, &value) in self.cells.iter().enumerate() {
+      memory.put_value(pointer as u32, M::value_from_u32(value));
+    }
+    *memory.pointer_mut() = self.pointer;
+    // --max-steps counts from where the program left off, like --timeout does
+    let runtime = memory.runtime_mut();
+    runtime.steps = self.steps;
+    runtime.max_steps = runtime.max_steps.map(|max_steps| max_steps.saturating_add(self.steps));
+    Ok(())
+  }
+
+  // the settings the program was started with, on top of the options given when resuming
+  pub fn args(&self, args: &Args) -> Result<Args, Error> {
+    let mut args = args.clone();
+    if self.file.is_empty() {
+      args.file = None;
+      args.inline = Some(self.source.clone());
+    } else {
+      args.file = Some(self.file.clone());
+      args.inline = None;
+    }
+    args.program_input = self.input.clone();
+    args.cell_size = self.cell_size.to_string();
+    args.memory_size = match self.memory_size.try_into() {
+      Err(_) => return Err(Error::InvalidState(self.file.clone())),
+      Ok(memory_size) => memory_size
+    };
+    args.wrap_around = self.flags & WRAP_AROUND != 0;
+    args.no_overflows = self.flags & NO_OVERFLOWS != 0;
+    args.unoptimised = self.flags & UNOPTIMISED != 0;
+    args.debug = self.flags & DEBUG != 0;
+    args.preprocess = self.flags & PREPROCESS != 0;
+    args.dialect = self.dialect.clone();
+    Ok(args)
+  }
+
+  pub fn encode(&self) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(self.file.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(self.file.as_bytes());
+    bytes.extend_from_slice(&(self.source.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(self.source.as_bytes());
+    bytes.extend_from_slice(&self.hash.to_le_bytes());
+    bytes.push(self.cell_size);
+    bytes.extend_from_slice(&self.memory_size.to_le_bytes());
+    bytes.push(self.flags);
+    bytes.extend_from_slice(&self.steps.to_le_bytes());
+    bytes.extend_from_slice(&self.pointer.to_le_bytes());
+    bytes.extend_from_slice(&(self.path.len() as u32).to_le_bytes());
+    for &index in &self.path {
+      bytes.extend_from_slice(&(index as u32).to_le_bytes());
+    }
+    let input = self.input.as_deref().unwrap_or_default();
+    bytes.push(self.input.is_some() as u8);
+    bytes.extend_from_slice(&(input.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(input.as_bytes());
+    bytes.extend_from_slice(&(self.cells.len() as u32).to_le_bytes());
+    for &cell in &self.cells {
+      bytes.extend_from_slice(&cell.to_le_bytes());
+    }
+    bytes.extend_from_slice(&(self.dialect.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(self.dialect.as_bytes());
+    bytes
+  }
+
+  pub fn decode(bytes: &[u8], path: &str) -> Result<State, Error> {
+    let invalid = || Error::InvalidState(path.to_string());
+    let mut decoder = Decoder { bytes, offset: 0 };
+    if decoder.take(MAGIC.len()).ok_or_else(invalid)? != MAGIC {
+      return Err(invalid());
+    }
+    let version = decoder.u32().ok_or_else(invalid)?;
+    if version != VERSION {
+      return Err(Error::UnsupportedStateVersion(version));
+    }
+    let state = State {
+      file: decoder.string().ok_or_else(invalid)?,
+      source: decoder.string().ok_or_else(invalid)?,
+      hash: decoder.u64().ok_or_else(invalid)?,
+      cell_size: decoder.u8().ok_or_else(invalid)?,
+      memory_size: decoder.u32().ok_or_else(invalid)?,
+      flags: decoder.u8().ok_or_else(invalid)?,
+      steps: decoder.u64().ok_or_else(invalid)?,
+      pointer: decoder.u32().ok_or_else(invalid)?,
+      path: {
+        let len = decoder.u32().ok_or_else(invalid)?;
+        (0..len).map(|_| decoder.u32().map(|index| index as usize)).collect::<Option<_>>().ok_or_else(invalid)?
+      },
+      input: {
+        let pending = decoder.u8().ok_or_else(invalid)? != 0;
+        let input = decoder.string().ok_or_else(invalid)?;
+        if pending { Some(input) } else { None }
+      },
+      cells: {
+        let len = decoder.u32().ok_or_else(invalid)?;
+        (0..len).map(|_| decoder.u32()).collect::<Option<_>>().ok_or_else(invalid)?
+      },
+      dialect: decoder.string().ok_or_else(invalid)?
+    };
+    if decoder.offset != bytes.len() || ![8, 16, 32].contains(&state.cell_size) || state.path.is_empty() {
+      Err(invalid())
+    } else {
+      Ok(state)
+    }
+  }
+
+  pub fn save(&self, path: &str) -> Result<(), Error> {
+    match fs::write(path, self.encode()) {
+      Err(_) => Err(Error::SaveStateFail(path.to_string())),
+      Ok(()) => Ok(())
+    }
+  }
+
+  pub fn load(path: &str) -> Result<State, Error> {
+    match fs::read(path) {
+      Err(_) => Err(Error::LoadStateFail(path.to_string())),
+      Ok(bytes) => State::decode(&bytes, path)
+    }
+  }
+}
+
+struct Decoder<'a> {
+  bytes: &'a [u8],
+  offset: usize
+}
+
+impl<'a> Decoder<'a> {
+  fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+    let bytes = self.bytes.get(self.offset..self.offset.checked_add(n)?)?;
+    self.offset += n;
+    Some(bytes)
+  }
+
+  fn u8(&mut self) -> Option<u8> {
+    Some(self.take(1)?[0])
+  }
+
+  fn u32(&mut self) -> Option<u32> {
+    Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+  }
+
+  fn u64(&mut self) -> Option<u64> {
+    Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+  }
+
+  fn string(&mut self) -> Option<String> {
+    let len = self.u32()? as usize;
+    String::from_utf8(self.take(len)?.to_vec()).ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{resume, start, Memory1, Memory8};
+  use clap::Clap;
+
+  fn state() -> State {
+    State {
+      file: String::from("hello.bf"),
+      source: String::new(),
+      hash: hash("+[.+]"),
+      cell_size: 16,
+      memory_size: 30000,
+      flags: WRAP_AROUND | PREPROCESS,
+      steps: 1234,
+      pointer: 2,
+      path: vec![1, 0],
+      input: Some(String::from("héllo")),
+      cells: vec![0, 65535, 72],
+      dialect: String::from("ook")
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    let state = state();
+    assert_eq!(State::decode(&state.encode(), "hello.state").unwrap(), state);
+    let state = State { file: String::new(), source: String::from("+[.+]"), input: None, cells: vec![], ..state };
+    assert_eq!(State::decode(&state.encode(), "hello.state").unwrap(), state);
+  }
+
+  #[test]
+  fn truncated() {
+    let bytes = state().encode();
+    for len in 0..bytes.len() {
+      assert!(matches!(State::decode(&bytes[..len], "hello.state"), Err(Error::InvalidState(_))), "decoded {} of {} bytes", len, bytes.len());
+    }
+  }
+
+  #[test]
+  fn trailing_bytes() {
+    let mut bytes = state().encode();
+    bytes.push(0);
+    assert!(matches!(State::decode(&bytes, "hello.state"), Err(Error::InvalidState(_))));
+  }
+
+  #[test]
+  fn corrupt() {
+    let mut bytes = state().encode();
+    bytes[0] = b'X';
+    assert!(matches!(State::decode(&bytes, "hello.state"), Err(Error::InvalidState(_))));
+    let mut bytes = state().encode();
+    bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&2u32.to_le_bytes());
+    assert!(matches!(State::decode(&bytes, "hello.state"), Err(Error::UnsupportedStateVersion(2))));
+    let bytes = State { cell_size: 7, ..state() }.encode();
+    assert!(matches!(State::decode(&bytes, "hello.state"), Err(Error::InvalidState(_))));
+    let bytes = State { path: vec![], ..state() }.encode();
+    assert!(matches!(State::decode(&bytes, "hello.state"), Err(Error::InvalidState(_))));
+  }
+
+  #[test]
+  fn bit_tapes_are_not_saved() {
+    let args = Args::parse_from(["brainfuck", "--dialect", "boolfuck"]);
+    let memory = Memory1::new(&args).unwrap();
+    assert!(matches!(State::capture(&memory, 0, &[0]), Err(Error::UnsupportedDialect(_, _))));
+    let args = Args::parse_from(["brainfuck"]);
+    let memory = Memory8::new(&args).unwrap();
+    assert!(State::capture(&memory, 0, &[0]).is_ok());
+  }
+
+  #[test]
+  fn resumed_steps_are_limited_from_where_they_left_off() {
+    let path = std::env::temp_dir().join(format!("brainfuck-resume-{}.state", std::process::id()));
+    let path = path.to_str().unwrap();
+    let args = Args::parse_from(["brainfuck", "-u", "--max-steps", "10", "--save-state", path]);
+    let result = start(&Args { inline: Some(String::from("++++++++++[>+<-]")), ..args }, None);
+    assert!(matches!(result, Err(Error::StepLimitReached(10))));
+    let result = resume(path, &Args::parse_from(["brainfuck", "--max-steps", "10", "--save-state", path]));
+    assert!(matches!(result, Err(Error::StepLimitReached(20))));
+    assert_eq!(State::load(path).unwrap().steps, 20);
+    let result = resume(path, &Args::parse_from(["brainfuck"]));
+    std::fs::remove_file(path).unwrap();
+    assert!(result.is_ok());
+  }
+}
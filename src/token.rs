@@ -1,4 +1,19 @@
-#[derive(Debug)]
+use crate::Dialect;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Position {
+  pub line: usize,
+  pub column: usize
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
   Increment,
   Decrement,
@@ -7,25 +22,128 @@ pub enum Token {
   Write,
   Read,
   EnterLoop,
-  ExitLoop
+  ExitLoop,
+  Fork,
+  StartProcedure,
+  EndProcedure,
+  Call,
+  End,
+  Store,
+  Load,
+  ShiftRight,
+  ShiftLeft,
+  Not,
+  Xor,
+  And,
+  Or,
+  Comment(String)
 }
 
-pub fn lex(program: &str) -> Vec<Token> {
+impl Token {
+  pub fn is_command(&self) -> bool {
+    !matches!(self, Token::Comment(_))
+  }
+
+  // how Brainfuck and its extensions spell the command
+  pub fn to_char(&self) -> Option<char> {
+    match self {
+      Token::Increment => Some('+'),
+      Token::Decrement => Some('-'),
+      Token::MoveRight => Some('>'),
+      Token::MoveLeft => Some('<'),
+      Token::Write => Some('.'),
+      Token::Read => Some(','),
+      Token::EnterLoop => Some('['),
+      Token::ExitLoop => Some(']'),
+      Token::Fork => Some('Y'),
+      Token::StartProcedure => Some('('),
+      Token::EndProcedure => Some(')'),
+      Token::Call => Some(':'),
+      Token::End => Some('@'),
+      Token::Store => Some('$'),
+      Token::Load => Some('!'),
+      Token::ShiftRight => Some('}'),
+      Token::ShiftLeft => Some('{'),
+      Token::Not => Some('~'),
+      Token::Xor => Some('^'),
+      Token::And => Some('&'),
+      Token::Or => Some('|'),
+      Token::Comment(_) => None
+    }
+  }
+}
+
+pub fn lex(program: &str) -> Vec<(Token, Position)> {
+  lex_dialect(program, &Dialect::brainfuck())
+}
+
+pub fn lex_with_comments(program: &str) -> Vec<(Token, Position)> {
+  lex_dialect_with_comments(program, &Dialect::brainfuck())
+}
+
+pub fn lex_dialect(program: &str, dialect: &Dialect) -> Vec<(Token, Position)> {
+  lex_dialect_with_comments(program, dialect).into_iter().filter(|(token, _)| token.is_command()).collect()
+}
+
+// every run of characters between two commands becomes a comment, whitespace included,
+// so the source can be rebuilt from the tokens
+pub fn lex_dialect_with_comments(program: &str, dialect: &Dialect) -> Vec<(Token, Position)> {
+  let chars: Vec<char> = program.chars().collect();
   let mut tokens = vec![];
-  for char in program.chars() {
-    if let Some(token) = match char {
-      '+' => Some(Token::Increment),
-      '-' => Some(Token::Decrement),
-      '>' => Some(Token::MoveRight),
-      '<' => Some(Token::MoveLeft),
-      '.' => Some(Token::Write),
-      ',' => Some(Token::Read),
-      '[' => Some(Token::EnterLoop),
-      ']' => Some(Token::ExitLoop),
-      _ => None
-    } {
-      tokens.push(token);
+  let mut position = Position { line: 1, column: 1 };
+  let mut comment: Option<(String, Position)> = None;
+  let mut i = 0;
+  while i < chars.len() {
+    let end = match dialect.command_at(&chars, i) {
+      Some((token, end)) => {
+        if let Some((text, start)) = comment.take() {
+          tokens.push((Token::Comment(text), start));
+        }
+        tokens.push((token, position));
+        end
+      }
+      None => {
+        comment.get_or_insert_with(|| (String::new(), position)).0.push(chars[i]);
+        i + 1
+      }
+    };
+    for &char in &chars[i..end] {
+      if char == '\n' {
+        position.line += 1;
+        position.column = 1;
+      } else {
+        position.column += 1;
+      }
     }
+    i = end;
+  }
+  if let Some((text, start)) = comment {
+    tokens.push((Token::Comment(text), start));
   }
   tokens
-}
\ No newline at end of file
+}
+
+// keeps the line breaks of comments but drops their text, which could spell a command in the other dialect
+pub fn convert(program: &str, from: &Dialect, to: &Dialect) -> String {
+  let mut converted = String::new();
+  let mut line_start = true;
+  for (token, _) in lex_dialect_with_comments(program, from) {
+    match token {
+      Token::Comment(text) => for _ in text.matches('\n') {
+        converted.push('\n');
+        line_start = true;
+      }
+      token => {
+        if !line_start {
+          converted += to.separator();
+        }
+        converted += &to.spelling(&token);
+        line_start = false;
+      }
+    }
+  }
+  if !converted.is_empty() && !converted.ends_with('\n') {
+    converted.push('\n');
+  }
+  converted
+}
@@ -0,0 +1,45 @@
+use crate::{Args, Error, Position};
+use std::io::{BufWriter, Write};
+use std::fs::File;
+
+#[derive(Debug)]
+pub struct Trace {
+  writer: BufWriter<File>,
+  every: u64,
+  from: u64,
+  to: u64
+}
+
+impl Trace {
+  pub fn new(args: &Args) -> Result<Option<Trace>, Error> {
+    match &args.trace {
+      None => Ok(None),
+      Some(path) => match File::create(path) {
+        Err(_) => Err(Error::CreateTraceFail(path.clone())),
+        Ok(file) => {
+          let mut writer = BufWriter::new(file);
+          if writeln!(writer, "step\tinstruction\tpointer\tvalue\tposition").is_err() {
+            return Err(Error::WriteTraceFail);
+          }
+          Ok(Some(Trace {
+            writer,
+            every: args.trace_every.get(),
+            from: args.trace_from.unwrap_or(1),
+            to: args.trace_to.unwrap_or(u64::MAX)
+          }))
+        }
+      }
+    }
+  }
+
+  pub fn wants(&self, step: u64) -> bool {
+    step >= self.from && step <= self.to && (step - self.from).is_multiple_of(self.every)
+  }
+
+  pub fn log(&mut self, step: u64, instruction: &str, pointer: u32, value: &str, position: Position) -> Result<(), Error> {
+    match writeln!(self.writer, "{}\t{}\t{}\t{}\t{}", step, instruction, pointer, value, position) {
+      Err(_) => Err(Error::WriteTraceFail),
+      Ok(()) => Ok(())
+    }
+  }
+}
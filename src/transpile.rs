@@ -0,0 +1,93 @@
+use crate::{Args, Instruction, Edit, Move};
+
+fn edit(edit: Edit) -> String {
+  match edit {
+    Edit::Increment(n) => format!("+= {}", n),
+    Edit::Decrement(n) => format!("-= {}", n)
+  }
+}
+
+fn cell(mov: Move) -> String {
+  match mov {
+    Move::Right(n) => format!("m[p + {}]", n),
+    Move::Left(n) => format!("m[p - {}]", n)
+  }
+}
+
+fn transpile_inner(instructions: &[Instruction], depth: usize, c: &mut String) {
+  let indent = "  ".repeat(depth);
+  for instruction in instructions {
+    match instruction {
+      Instruction::Loop(instructions, _) => {
+        *c += &format!("{}while (m[p]) {{\n", indent);
+        transpile_inner(instructions, depth + 1, c);
+        *c += &format!("{}}}\n", indent);
+      }
+      Instruction::InfiniteLoop(_, position) => {
+        *c += &format!("{}if (m[p]) {{\n", indent);
+        *c += &format!("{}  fputs(\"runtime error: The loop at position {} never terminates\\n\", stderr);\n", indent, position);
+        *c += &format!("{}  exit(1);\n", indent);
+        *c += &format!("{}}}\n", indent);
+      }
+      Instruction::EditOtherLoop(mov, edit, _) => {
+        let (sign, n) = match *edit {
+          Edit::Increment(n) => ('+', n),
+          Edit::Decrement(n) => ('-', n)
+        };
+        *c += &format!("{}{} {}= {} * m[p];\n", indent, cell(*mov), sign, n);
+        *c += &format!("{}m[p] = 0;\n", indent);
+      }
+      Instruction::MoveEdit(mov, e, _) => {
+        *c += &format!("{}p {};\n", indent, match *mov {
+          Move::Right(n) => format!("+= {}", n),
+          Move::Left(n) => format!("-= {}", n)
+        });
+        *c += &format!("{}m[p] {};\n", indent, edit(*e));
+      }
+      Instruction::EditOther(mov, e, _) => *c += &format!("{}{} {};\n", indent, cell(*mov), edit(*e)),
+      Instruction::Edit(e, _) => *c += &format!("{}m[p] {};\n", indent, edit(*e)),
+      Instruction::Move(Move::Right(n), _) => *c += &format!("{}p += {};\n", indent, n),
+      Instruction::Move(Move::Left(n), _) => *c += &format!("{}p -= {};\n", indent, n),
+      Instruction::Clear(_) => *c += &format!("{}m[p] = 0;\n", indent),
+      Instruction::Write(_) => *c += &format!("{}put(m[p]);\n", indent),
+      Instruction::Read(_) => *c += &format!("{}get(&m[p]);\n", indent),
+      Instruction::Fork(_)
+      | Instruction::Procedure(_, _)
+      | Instruction::Call(_)
+      | Instruction::End(_)
+      | Instruction::Store(_)
+      | Instruction::Load(_)
+      | Instruction::Bitwise(_, _) => unreachable!("programs using extensions aren't transpiled")
+    }
+  }
+}
+
+// cells wrap around like they do without --no-overflows, but the pointer isn't checked
+pub fn transpile_c(instructions: &[Instruction], args: &Args) -> String {
+  let mut c = String::new();
+  c += "#include <stdint.h>\n#include <stdio.h>\n#include <stdlib.h>\n\n";
+  c += &format!("typedef uint{}_t cell;\n\n", args.cell_size);
+  c += &format!("static cell m[{}];\n\n", args.memory_size);
+  // characters are written and read as UTF-8 like the interpreter does, whatever the cell size
+  c += "static void put(cell value) {\n  uint32_t v = value;\n";
+  c += "  if (v < 0x80) {\n    putchar(v);\n";
+  c += "  } else if (v < 0x800) {\n    putchar(0xC0 | v >> 6);\n    putchar(0x80 | (v & 0x3F));\n";
+  c += "  } else if (v < 0x10000) {\n    putchar(0xE0 | v >> 12);\n    putchar(0x80 | (v >> 6 & 0x3F));\n    putchar(0x80 | (v & 0x3F));\n";
+  c += "  } else {\n    putchar(0xF0 | v >> 18);\n    putchar(0x80 | (v >> 12 & 0x3F));\n    putchar(0x80 | (v >> 6 & 0x3F));\n    putchar(0x80 | (v & 0x3F));\n  }\n";
+  c += "}\n\n";
+  c += "static void get(cell *value) {\n  int c, n;\n  uint32_t v;\n  fflush(stdout);\n  c = getchar();\n";
+  c += "  if (c == EOF) {\n    fputs(\"runtime error: Expected input, got none\\n\", stderr);\n    exit(1);\n  }\n";
+  c += "  n = c >= 0xF0 ? 3 : c >= 0xE0 ? 2 : c >= 0xC0 ? 1 : 0;\n";
+  c += "  v = n ? c & 0x3F >> n : c;\n";
+  c += "  while (n-- > 0 && (c = getchar()) != EOF) {\n    v = v << 6 | (c & 0x3F);\n  }\n";
+  let bits: u32 = args.cell_size.parse().unwrap_or(8);
+  if bits < 32 {
+    c += &format!("  if (v > {}) {{\n", u32::MAX >> (32 - bits));
+    c += &format!("    fputs(\"runtime error: Storing the input character requires more than {} bits\\n\", stderr);\n    exit(1);\n  }}\n", bits);
+  }
+  c += "  *value = v;\n}\n\n";
+  c += "int main(void) {\n  size_t p = 0;\n";
+  transpile_inner(instructions, 1, &mut c);
+  c += "  return 0;\n}\n";
+  c
+}
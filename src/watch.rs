@@ -0,0 +1,79 @@
+use std::str::FromStr;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+  Change,
+  Equals(u32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+  pub from: u32,
+  pub to: u32,
+  pub condition: Condition,
+  pub pause: bool
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+  pub watchpoint: Watchpoint,
+  pub step: u64,
+  pub pointer: u32,
+  pub previous: u32,
+  pub value: u32
+}
+
+impl Watchpoint {
+  pub fn matches(&self, pointer: u32, previous: u32, value: u32) -> bool {
+    pointer >= self.from && pointer <= self.to && match self.condition {
+      Condition::Change => previous != value,
+      Condition::Equals(expected) => previous != expected && value == expected
+    }
+  }
+}
+
+// cells are written as `42` or `10-20`, optionally followed by `=value`
+impl FromStr for Watchpoint {
+  type Err = String;
+
+  fn from_str(spec: &str) -> Result<Watchpoint, String> {
+    let invalid = || format!("'{}' isn't a valid watchpoint, expected <cell>[-<cell>][=<value>]", spec);
+    let (cells, condition) = match spec.split_once('=') {
+      None => (spec, Condition::Change),
+      Some((cells, value)) => (cells, Condition::Equals(value.trim().parse().map_err(|_| invalid())?))
+    };
+    let (from, to) = match cells.split_once('-') {
+      None => {
+        let cell = cells.trim().parse().map_err(|_| invalid())?;
+        (cell, cell)
+      }
+      Some((from, to)) => (from.trim().parse().map_err(|_| invalid())?, to.trim().parse().map_err(|_| invalid())?)
+    };
+    if from > to {
+      Err(invalid())
+    } else {
+      Ok(Watchpoint { from, to, condition, pause: true })
+    }
+  }
+}
+
+impl fmt::Display for Watchpoint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.from == self.to {
+      write!(f, "cell {}", self.from)?;
+    } else {
+      write!(f, "cells {} to {}", self.from, self.to)?;
+    }
+    match self.condition {
+      Condition::Change => write!(f, " changes"),
+      Condition::Equals(value) => write!(f, " becomes {}", value)
+    }
+  }
+}
+
+impl fmt::Display for Hit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "cell {} went from {} to {} at step {}", self.pointer, self.previous, self.value, self.step)
+  }
+}